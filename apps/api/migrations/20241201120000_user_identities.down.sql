@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS OIDC_LOGIN_REQUESTS;
+DROP TABLE IF EXISTS USER_IDENTITIES;
@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS OIDC_LOGIN_REQUESTS;
+DROP TABLE IF EXISTS USER_IDENTITIES;
+
+-- external identities (e.g. an organizational identity provider) linked to a local user
+CREATE TABLE USER_IDENTITIES (
+    PROVIDER VARCHAR(64) NOT NULL,
+    SUBJECT VARCHAR(255) NOT NULL,
+    USERNAME VARCHAR(32) NOT NULL,
+    EMAIL VARCHAR(512),
+    CREATED_AT TIMESTAMP DEFAULT NOW(),
+    UPDATED_AT TIMESTAMP DEFAULT NOW(),
+
+    PRIMARY KEY (PROVIDER, SUBJECT),
+    CONSTRAINT USERNAME_FK FOREIGN KEY (USERNAME) REFERENCES USERS(USERNAME) ON DELETE CASCADE
+);
+
+-- pending authorization code requests, consumed once on callback
+CREATE TABLE OIDC_LOGIN_REQUESTS (
+    STATE VARCHAR(64) PRIMARY KEY,
+    CODE_VERIFIER VARCHAR(128) NOT NULL,
+    EXPIRY_DATE TIMESTAMP NOT NULL,
+    CREATED_AT TIMESTAMP DEFAULT NOW()
+);
@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS PERSONAL_ACCESS_TOKENS;
+
+-- tokens are only shown once on creation, only the sha256 hash of it is stored
+CREATE TABLE PERSONAL_ACCESS_TOKENS (
+    ID VARCHAR(32) PRIMARY KEY,
+    USERNAME VARCHAR(32) NOT NULL,
+    NAME VARCHAR(64) NOT NULL,
+    TOKEN_HASH VARCHAR(64) NOT NULL UNIQUE,
+    SCOPES TEXT[] NOT NULL,
+    EXPIRY_DATE TIMESTAMP,
+    LAST_USED_AT TIMESTAMP,
+    CREATED_AT TIMESTAMP DEFAULT NOW(),
+    UPDATED_AT TIMESTAMP DEFAULT NOW(),
+
+    CONSTRAINT USERNAME_FK FOREIGN KEY (USERNAME) REFERENCES USERS(USERNAME) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_personal_access_tokens_username ON PERSONAL_ACCESS_TOKENS (USERNAME);
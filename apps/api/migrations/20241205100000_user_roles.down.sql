@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE USERS DROP COLUMN IF EXISTS DISABLED_AT;
+ALTER TABLE USERS DROP COLUMN IF EXISTS ROLE;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+ALTER TABLE USERS DROP COLUMN IF EXISTS ROLE;
+ALTER TABLE USERS DROP COLUMN IF EXISTS DISABLED_AT;
+
+-- permissions of each role are defined on the application, see auth/role.rs
+ALTER TABLE USERS ADD COLUMN ROLE VARCHAR(16) NOT NULL DEFAULT 'user'
+    CONSTRAINT ROLE_CHECK CHECK (ROLE IN ('user', 'moderator', 'admin'));
+ALTER TABLE USERS ADD COLUMN DISABLED_AT TIMESTAMP;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS AUDIT_LOGS;
+
+-- append only, ACTOR is kept without a foreign key so entries outlive deleted users
+-- ACTOR and TARGET can come from the request (tried usernames, paths) so they are unbounded
+CREATE TABLE AUDIT_LOGS (
+    ID VARCHAR(32) PRIMARY KEY,
+    EVENT VARCHAR(32) NOT NULL,
+    ACTOR TEXT,
+    TARGET TEXT,
+    IP_ADDRESS VARCHAR(64),
+    USER_AGENT TEXT,
+    DETAILS TEXT,
+    CREATED_AT TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_logs_created_at ON AUDIT_LOGS (CREATED_AT);
+CREATE INDEX idx_audit_logs_actor ON AUDIT_LOGS (ACTOR, CREATED_AT);
+CREATE INDEX idx_audit_logs_event ON AUDIT_LOGS (EVENT, CREATED_AT);
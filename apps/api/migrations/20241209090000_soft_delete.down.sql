@@ -0,0 +1,12 @@
+-- Add down migration script here
+ALTER TABLE USER_ROADMAP_AREAS DROP CONSTRAINT AREA_ID_FK;
+ALTER TABLE USER_ROADMAP_AREAS ADD CONSTRAINT AREA_ID_FK
+    FOREIGN KEY (AREA_ID) REFERENCES ROADMAP_AREAS(ID);
+ALTER TABLE USER_ROADMAP_AREAS DROP CONSTRAINT USER_ROADMAP_ID_FK;
+ALTER TABLE USER_ROADMAP_AREAS ADD CONSTRAINT USER_ROADMAP_ID_FK
+    FOREIGN KEY (USER_ROADMAP_ID) REFERENCES USER_ROADMAPS(ID);
+
+DROP INDEX IF EXISTS idx_roadmap_areas_deleted_at;
+DROP INDEX IF EXISTS idx_roadmaps_deleted_at;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS DELETED_AT;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS DELETED_AT;
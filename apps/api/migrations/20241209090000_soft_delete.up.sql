@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS DELETED_AT;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS DELETED_AT;
+
+-- deleted rows are kept in the trash until purged after the retention window, areas deleted
+-- together share the same DELETED_AT so the whole subtree can be restored at once
+ALTER TABLE ROADMAPS ADD COLUMN DELETED_AT TIMESTAMP;
+ALTER TABLE ROADMAP_AREAS ADD COLUMN DELETED_AT TIMESTAMP;
+
+CREATE INDEX idx_roadmaps_deleted_at ON ROADMAPS (DELETED_AT) WHERE DELETED_AT IS NOT NULL;
+CREATE INDEX idx_roadmap_areas_deleted_at ON ROADMAP_AREAS (DELETED_AT) WHERE DELETED_AT IS NOT NULL;
+
+-- purging removes the rows for good, which should take the progress on them along
+ALTER TABLE USER_ROADMAP_AREAS DROP CONSTRAINT USER_ROADMAP_ID_FK;
+ALTER TABLE USER_ROADMAP_AREAS ADD CONSTRAINT USER_ROADMAP_ID_FK
+    FOREIGN KEY (USER_ROADMAP_ID) REFERENCES USER_ROADMAPS(ID) ON DELETE CASCADE;
+ALTER TABLE USER_ROADMAP_AREAS DROP CONSTRAINT AREA_ID_FK;
+ALTER TABLE USER_ROADMAP_AREAS ADD CONSTRAINT AREA_ID_FK
+    FOREIGN KEY (AREA_ID) REFERENCES ROADMAP_AREAS(ID) ON DELETE CASCADE;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS PUBLISHED_AT;
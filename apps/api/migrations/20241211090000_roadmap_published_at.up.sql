@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS PUBLISHED_AT;
+
+ALTER TABLE ROADMAPS ADD COLUMN PUBLISHED_AT TIMESTAMP;
+UPDATE ROADMAPS SET PUBLISHED_AT = UPDATED_AT WHERE PUBLISHED = TRUE;
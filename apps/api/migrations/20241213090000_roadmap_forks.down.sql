@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_roadmaps_forked_from;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS FORKED_FROM;
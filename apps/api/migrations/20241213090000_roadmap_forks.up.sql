@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS FORKED_FROM;
+
+-- forks are independent copies, the upstream is only kept for attribution
+ALTER TABLE ROADMAPS ADD COLUMN FORKED_FROM VARCHAR(32)
+    REFERENCES ROADMAPS(ID) ON DELETE SET NULL;
+
+CREATE INDEX idx_roadmaps_forked_from ON ROADMAPS (FORKED_FROM);
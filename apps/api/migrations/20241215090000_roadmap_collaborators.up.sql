@@ -0,0 +1,23 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS ROADMAP_COLLABORATORS;
+
+-- the publisher is the owner of the roadmap and is never listed here, invitations are pending
+-- until ACCEPTED_AT is set
+CREATE TABLE ROADMAP_COLLABORATORS (
+    ROADMAP_ID VARCHAR(32) NOT NULL,
+    USERNAME VARCHAR(32) NOT NULL,
+    ROLE VARCHAR(16) NOT NULL
+        CONSTRAINT COLLABORATOR_ROLE_CHECK CHECK (ROLE IN ('editor', 'viewer')),
+    INVITED_BY VARCHAR(32),
+    ACCEPTED_AT TIMESTAMP,
+    CREATED_AT TIMESTAMP DEFAULT NOW(),
+    UPDATED_AT TIMESTAMP DEFAULT NOW(),
+
+    PRIMARY KEY (ROADMAP_ID, USERNAME),
+    CONSTRAINT ROADMAP_ID_FK FOREIGN KEY (ROADMAP_ID) REFERENCES ROADMAPS(ID) ON DELETE CASCADE,
+    CONSTRAINT USERNAME_FK FOREIGN KEY (USERNAME) REFERENCES USERS(USERNAME) ON DELETE CASCADE,
+    CONSTRAINT INVITED_BY_FK FOREIGN KEY (INVITED_BY) REFERENCES USERS(USERNAME) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_roadmap_collaborators_username ON ROADMAP_COLLABORATORS (USERNAME);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS SHARE_TOKEN;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS VISIBILITY;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS VISIBILITY;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS SHARE_TOKEN;
+
+-- visibility applies once a roadmap is published, drafts stay with the owner and collaborators.
+-- unlisted roadmaps are only reachable with the share token, which is kept as is so the owner
+-- can copy the link again
+ALTER TABLE ROADMAPS ADD COLUMN VISIBILITY VARCHAR(16) NOT NULL DEFAULT 'public'
+    CONSTRAINT VISIBILITY_CHECK CHECK (VISIBILITY IN ('private', 'unlisted', 'public'));
+ALTER TABLE ROADMAPS ADD COLUMN SHARE_TOKEN VARCHAR(64) UNIQUE;
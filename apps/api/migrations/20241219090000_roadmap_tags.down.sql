@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS ROADMAP_TAGS;
+DROP TABLE IF EXISTS TAGS;
@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS ROADMAP_TAGS;
+DROP TABLE IF EXISTS TAGS;
+
+-- names are normalized by the application (lowercase, dash separated), see roadmap/tag.rs
+CREATE TABLE TAGS (
+    ID VARCHAR(32) PRIMARY KEY,
+    NAME VARCHAR(32) NOT NULL UNIQUE,
+    CREATED_AT TIMESTAMP DEFAULT NOW()
+);
+
+CREATE TABLE ROADMAP_TAGS (
+    ROADMAP_ID VARCHAR(32) NOT NULL,
+    TAG_ID VARCHAR(32) NOT NULL,
+    CREATED_AT TIMESTAMP DEFAULT NOW(),
+
+    PRIMARY KEY (ROADMAP_ID, TAG_ID),
+    CONSTRAINT ROADMAP_ID_FK FOREIGN KEY (ROADMAP_ID) REFERENCES ROADMAPS(ID) ON DELETE CASCADE,
+    CONSTRAINT TAG_ID_FK FOREIGN KEY (TAG_ID) REFERENCES TAGS(ID) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_roadmap_tags_tag_id ON ROADMAP_TAGS (TAG_ID);
+-- prefix search for autocomplete
+CREATE INDEX idx_tags_name_prefix ON TAGS (NAME VARCHAR_PATTERN_OPS);
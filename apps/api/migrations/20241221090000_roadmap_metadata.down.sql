@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_roadmaps_difficulty;
+DROP INDEX IF EXISTS idx_roadmaps_content_language;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS ESTIMATED_HOURS;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS PREREQUISITE_SUMMARY;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS CONTENT_LANGUAGE;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS ESTIMATED_HOURS;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS DIFFICULTY;
@@ -0,0 +1,22 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS DIFFICULTY;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS ESTIMATED_HOURS;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS CONTENT_LANGUAGE;
+ALTER TABLE ROADMAPS DROP COLUMN IF EXISTS PREREQUISITE_SUMMARY;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS ESTIMATED_HOURS;
+
+ALTER TABLE ROADMAPS ADD COLUMN DIFFICULTY VARCHAR(16)
+    CONSTRAINT DIFFICULTY_CHECK CHECK (DIFFICULTY IN ('beginner', 'intermediate', 'advanced'));
+-- the author's own estimate, the sum of the areas is used when it's missing
+ALTER TABLE ROADMAPS ADD COLUMN ESTIMATED_HOURS DOUBLE PRECISION
+    CONSTRAINT ESTIMATED_HOURS_CHECK CHECK (ESTIMATED_HOURS >= 0);
+-- lowercase language tag, e.g. en or pt-br
+ALTER TABLE ROADMAPS ADD COLUMN CONTENT_LANGUAGE VARCHAR(16);
+ALTER TABLE ROADMAPS ADD COLUMN PREREQUISITE_SUMMARY TEXT;
+
+ALTER TABLE ROADMAP_AREAS ADD COLUMN ESTIMATED_HOURS DOUBLE PRECISION
+    CONSTRAINT ESTIMATED_HOURS_CHECK CHECK (ESTIMATED_HOURS >= 0);
+
+CREATE INDEX idx_roadmaps_difficulty ON ROADMAPS (DIFFICULTY);
+CREATE INDEX idx_roadmaps_content_language ON ROADMAPS (CONTENT_LANGUAGE);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS ESTIMATED_HOURS_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS POSITION_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS DESCRIPTION_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS TITLE_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS PARENT_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS REVISION;
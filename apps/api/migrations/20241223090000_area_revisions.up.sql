@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS PARENT_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS TITLE_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS DESCRIPTION_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS POSITION_REVISION;
+ALTER TABLE ROADMAP_AREAS DROP COLUMN IF EXISTS ESTIMATED_HOURS_REVISION;
+
+-- bumped on every change of the area, editors send the revision their change is based on
+ALTER TABLE ROADMAP_AREAS ADD COLUMN REVISION BIGINT NOT NULL DEFAULT 1;
+-- revision each field was last changed at, a stale change is only rejected when it touches a
+-- field changed after the revision it is based on
+ALTER TABLE ROADMAP_AREAS ADD COLUMN PARENT_REVISION BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE ROADMAP_AREAS ADD COLUMN TITLE_REVISION BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE ROADMAP_AREAS ADD COLUMN DESCRIPTION_REVISION BIGINT NOT NULL DEFAULT 1;
+-- X and Y always change together
+ALTER TABLE ROADMAP_AREAS ADD COLUMN POSITION_REVISION BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE ROADMAP_AREAS ADD COLUMN ESTIMATED_HOURS_REVISION BIGINT NOT NULL DEFAULT 1;
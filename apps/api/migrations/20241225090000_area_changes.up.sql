@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+DROP TABLE IF EXISTS AREA_CHANGES;
+
+-- append only, AREA_ID and AUTHOR are kept without a foreign key so the history outlives
+-- purged areas and deleted users
+CREATE TABLE AREA_CHANGES (
+    -- changes are ordered by ID, timestamps can be equal
+    ID BIGSERIAL PRIMARY KEY,
+    ROADMAP_ID VARCHAR(32) NOT NULL REFERENCES ROADMAPS(ID) ON DELETE CASCADE,
+    AREA_ID VARCHAR(32) NOT NULL,
+    AUTHOR VARCHAR(32) NOT NULL,
+    -- create, update, delete or restore
+    KIND VARCHAR(16) NOT NULL,
+    -- edit, undo, redo or skip, skips mark their target as not revertable
+    SOURCE VARCHAR(16) NOT NULL,
+    -- change undone, redone or skipped
+    TARGET_ID BIGINT REFERENCES AREA_CHANGES(ID) ON DELETE CASCADE,
+    -- the area before and after the change, BEFORE is null on creations and AFTER on deletions
+    BEFORE JSONB,
+    AFTER JSONB,
+    CREATED_AT TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_area_changes_roadmap ON AREA_CHANGES (ROADMAP_ID, ID);
+CREATE INDEX idx_area_changes_author ON AREA_CHANGES (ROADMAP_ID, AUTHOR, ID);
+CREATE INDEX idx_area_changes_target ON AREA_CHANGES (TARGET_ID);
@@ -0,0 +1,42 @@
+-- Add up migration script here
+
+-- roadmaps matching the public search, shared by the search and its tag facets so both always
+-- apply the same filter. Parameters are prefixed as column names would take precedence over them
+CREATE OR REPLACE FUNCTION SEARCH_ROADMAPS(
+    SEARCH_TITLE TEXT,
+    -- roadmaps have to carry every one of them
+    SEARCH_TAGS TEXT[],
+    -- the filters below match everything when null
+    SEARCH_DIFFICULTY TEXT,
+    SEARCH_CONTENT_LANGUAGE TEXT,
+    SEARCH_MIN_HOURS DOUBLE PRECISION,
+    SEARCH_MAX_HOURS DOUBLE PRECISION
+) RETURNS SETOF ROADMAPS
+LANGUAGE SQL STABLE
+AS $$
+    SELECT R.*
+    FROM ROADMAPS R
+    WHERE
+        (R.TITLE = '' OR R.TITLE ILIKE SEARCH_TITLE) AND
+        R.PUBLISHED = TRUE AND
+        R.VISIBILITY = 'public' AND
+        R.DELETED_AT IS NULL AND
+        (
+            SELECT COUNT(*) FROM ROADMAP_TAGS RT
+            JOIN TAGS T ON T.ID = RT.TAG_ID
+            WHERE RT.ROADMAP_ID = R.ID AND T.NAME = ANY(SEARCH_TAGS)
+        ) = CARDINALITY(SEARCH_TAGS) AND
+        (SEARCH_DIFFICULTY IS NULL OR R.DIFFICULTY = SEARCH_DIFFICULTY) AND
+        (SEARCH_CONTENT_LANGUAGE IS NULL OR R.CONTENT_LANGUAGE = SEARCH_CONTENT_LANGUAGE) AND
+        (
+            (SEARCH_MIN_HOURS IS NULL AND SEARCH_MAX_HOURS IS NULL) OR
+            COALESCE(
+                R.ESTIMATED_HOURS,
+                (
+                    SELECT SUM(A.ESTIMATED_HOURS) FROM ROADMAP_AREAS A
+                    WHERE A.ROADMAP_ID = R.ID AND A.DELETED_AT IS NULL
+                ),
+                0
+            ) BETWEEN COALESCE(SEARCH_MIN_HOURS, 0) AND COALESCE(SEARCH_MAX_HOURS, 'Infinity')
+        )
+$$;
@@ -0,0 +1,277 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{Response, StatusCode},
+    Json,
+};
+use chrono::NaiveDateTime;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{record_audit_event, AuditEvent, RequestContext},
+    auth::{
+        extractor::{
+            Authorized, CanManageUsers, CanModerateRoadmaps, CanRevokeSessions, CanViewUsers,
+        },
+        role::Role,
+    },
+    router_common::{CreateResponse, RouterGlobalState},
+};
+
+use super::{get_users, revoke_user_sessions, unpublish_roadmap, update_user, UserSummary};
+
+const DEFAULT_LIMIT: i64 = 20;
+const DEFAULT_OFFSET: i64 = 0;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct GetUsersParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateUserRequest {
+    role: Option<Role>,
+    disabled: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserSummaryResponse {
+    username: String,
+    email: String,
+    name: String,
+    role: String,
+    disabled_at: Option<NaiveDateTime>,
+    created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GetUsersResult {
+    users: Vec<UserSummaryResponse>,
+    limit: i64,
+    offset: i64,
+}
+
+impl From<UserSummary> for UserSummaryResponse {
+    fn from(user: UserSummary) -> Self {
+        UserSummaryResponse {
+            username: user.username,
+            email: user.email,
+            name: user.name,
+            role: user.role,
+            disabled_at: user.disabled_at,
+            created_at: user.created_at,
+        }
+    }
+}
+
+fn json_response(
+    status: StatusCode,
+    is_successful: bool,
+    message: &str,
+    id: Option<String>,
+) -> Response<Body> {
+    let response = serde_json::to_string(&CreateResponse {
+        is_successful,
+        message: String::from(message),
+        id,
+    })
+    .unwrap();
+
+    Response::builder()
+        .status(status)
+        .body(Body::from(response))
+        .unwrap()
+}
+
+pub async fn get_users_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    _: Authorized<CanViewUsers>,
+    Query(params): Query<GetUsersParams>,
+) -> Response<Body> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(DEFAULT_OFFSET).max(0);
+
+    match get_users(limit, offset, &pool).await {
+        Ok(users) => {
+            let response = serde_json::to_string(&GetUsersResult {
+                users: users.into_iter().map(UserSummaryResponse::from).collect(),
+                limit,
+                offset,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[admin][get_users] error on getting users with cause {}",
+                err
+            );
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                false,
+                "Fail on getting users please try again",
+                None,
+            )
+        }
+    }
+}
+
+pub async fn update_user_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    Authorized { user: admin, .. }: Authorized<CanManageUsers>,
+    context: RequestContext,
+    Path(username): Path<String>,
+    Json(request): Json<UpdateUserRequest>,
+) -> Response<Body> {
+    // prevents admins from locking themselves out
+    if admin.username == username {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            false,
+            "You can't change the role or status of your own account",
+            None,
+        );
+    }
+
+    if request.role.is_none() && request.disabled.is_none() {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            false,
+            "Nothing to update, set either role or disabled",
+            None,
+        );
+    }
+
+    match update_user(&username, request.role, request.disabled, &pool).await {
+        Ok(false) => {
+            return json_response(StatusCode::NOT_FOUND, false, "User not found", None);
+        }
+        Ok(true) => {}
+        Err(err) => {
+            error!(
+                "[admin][update_user] error on updating user with cause {}",
+                err
+            );
+            return json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                false,
+                "Fail on updating user please try again",
+                None,
+            );
+        }
+    }
+
+    if let Some(role) = request.role {
+        info!(
+            "[admin][update_user] {} set role of {} to {}",
+            admin.username,
+            username,
+            role.as_str()
+        );
+    }
+    if let Some(disabled) = request.disabled {
+        info!(
+            "[admin][update_user] {} set disabled of {} to {}",
+            admin.username, username, disabled
+        );
+    }
+    // the session is already rejected while disabled, it's revoked so it can't be refreshed
+    if request.disabled == Some(true) {
+        record_audit_event(
+            AuditEvent::SessionRevoked,
+            Some(&admin.username),
+            Some(&username),
+            Some("account disabled"),
+            &context,
+            &pool,
+        )
+        .await;
+    }
+
+    json_response(StatusCode::OK, true, "User updated", Some(username))
+}
+
+pub async fn revoke_user_sessions_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    Authorized { user: admin, .. }: Authorized<CanRevokeSessions>,
+    context: RequestContext,
+    Path(username): Path<String>,
+) -> Response<Body> {
+    match revoke_user_sessions(&username, &pool).await {
+        Ok(result) => {
+            info!(
+                "[admin][revoke_user_sessions] {} revoked {} session of {}",
+                admin.username,
+                result.rows_affected(),
+                username
+            );
+            record_audit_event(
+                AuditEvent::SessionRevoked,
+                Some(&admin.username),
+                Some(&username),
+                None,
+                &context,
+                &pool,
+            )
+            .await;
+            json_response(StatusCode::OK, true, "Sessions revoked", Some(username))
+        }
+        Err(err) => {
+            error!(
+                "[admin][revoke_user_sessions] error on revoking sessions with cause {}",
+                err
+            );
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                false,
+                "Fail on revoking sessions please try again",
+                None,
+            )
+        }
+    }
+}
+
+pub async fn unpublish_roadmap_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    Authorized {
+        user: moderator, ..
+    }: Authorized<CanModerateRoadmaps>,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    match unpublish_roadmap(&roadmap_id, &pool).await {
+        Ok(result) if result.rows_affected() == 0 => {
+            json_response(StatusCode::NOT_FOUND, false, "Roadmap not found", None)
+        }
+        Ok(_) => {
+            info!(
+                "[admin][unpublish_roadmap] {} unpublished roadmap {}",
+                moderator.username, roadmap_id
+            );
+            json_response(
+                StatusCode::OK,
+                true,
+                "Roadmap unpublished",
+                Some(roadmap_id),
+            )
+        }
+        Err(err) => {
+            error!(
+                "[admin][unpublish_roadmap] error on unpublishing roadmap with cause {}",
+                err
+            );
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                false,
+                "Fail on unpublishing roadmap please try again",
+                None,
+            )
+        }
+    }
+}
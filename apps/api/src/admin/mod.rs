@@ -0,0 +1,133 @@
+use chrono::NaiveDateTime;
+use sqlx::{
+    postgres::{PgPool, PgQueryResult},
+    PgExecutor,
+};
+
+use crate::auth::role::Role;
+
+pub mod api;
+mod test;
+
+#[derive(Debug)]
+pub struct UserSummary {
+    pub username: String,
+    pub email: String,
+    pub name: String,
+    pub role: String,
+    pub disabled_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+async fn get_users(
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> Result<Vec<UserSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSummary,
+        r#"
+            SELECT USERNAME, EMAIL, NAME, ROLE, DISABLED_AT, CREATED_AT
+            FROM USERS
+            ORDER BY CREATED_AT, USERNAME
+            LIMIT $1
+            OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn set_user_role(
+    username: &String,
+    role: Role,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE USERS SET ROLE = $1, UPDATED_AT = NOW() WHERE USERNAME = $2",
+        role.as_str(),
+        username
+    )
+    .execute(executor)
+    .await
+}
+
+async fn set_user_disabled(
+    username: &String,
+    disabled: bool,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE USERS
+            SET
+                DISABLED_AT = CASE WHEN $1 THEN COALESCE(DISABLED_AT, NOW()) ELSE NULL END,
+                UPDATED_AT = NOW()
+            WHERE USERNAME = $2
+        "#,
+        disabled,
+        username
+    )
+    .execute(executor)
+    .await
+}
+
+async fn revoke_user_sessions(
+    username: &String,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!("DELETE FROM USERS_SESSION WHERE USERNAME = $1", username)
+        .execute(executor)
+        .await
+}
+
+/// Sets the role and disabled status given, all together or not at all. Disabling the user also
+/// revokes their session so it can't be refreshed. Returns false when the user doesn't exist.
+async fn update_user(
+    username: &String,
+    role: Option<Role>,
+    disabled: Option<bool>,
+    pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    if let Some(role) = role {
+        if set_user_role(username, role, &mut *transaction)
+            .await?
+            .rows_affected()
+            == 0
+        {
+            return Ok(false);
+        }
+    }
+
+    if let Some(disabled) = disabled {
+        if set_user_disabled(username, disabled, &mut *transaction)
+            .await?
+            .rows_affected()
+            == 0
+        {
+            return Ok(false);
+        }
+        if disabled {
+            revoke_user_sessions(username, &mut *transaction).await?;
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+async fn unpublish_roadmap(
+    roadmap_id: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        "UPDATE ROADMAPS SET PUBLISHED = FALSE, PUBLISHED_AT = NULL, UPDATED_AT = NOW() WHERE ID = $1",
+        roadmap_id
+    )
+    .execute(pool)
+    .await
+}
@@ -0,0 +1,109 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{Response, StatusCode},
+    response::IntoResponse,
+};
+use chrono::NaiveDateTime;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::extractor::{Authorized, CanViewAuditLogs},
+    router_common::{ApiError, RouterGlobalState},
+};
+
+use super::{find_audit_logs, AuditEvent, AuditLog, AuditLogFilter};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+const DEFAULT_OFFSET: i64 = 0;
+
+#[derive(Deserialize)]
+pub struct GetAuditLogsParams {
+    event: Option<AuditEvent>,
+    actor: Option<String>,
+    target: Option<String>,
+    // e.g. 2024-12-01T00:00:00, in UTC
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AuditLogResponse {
+    id: String,
+    event: String,
+    actor: Option<String>,
+    target: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    details: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GetAuditLogsResult {
+    audit_logs: Vec<AuditLogResponse>,
+    limit: i64,
+    offset: i64,
+}
+
+impl From<AuditLog> for AuditLogResponse {
+    fn from(audit_log: AuditLog) -> Self {
+        AuditLogResponse {
+            id: audit_log.id,
+            event: audit_log.event,
+            actor: audit_log.actor,
+            target: audit_log.target,
+            ip_address: audit_log.ip_address,
+            user_agent: audit_log.user_agent,
+            details: audit_log.details,
+            created_at: audit_log.created_at,
+        }
+    }
+}
+
+pub async fn get_audit_logs_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    _: Authorized<CanViewAuditLogs>,
+    Query(params): Query<GetAuditLogsParams>,
+) -> Response<Body> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(DEFAULT_OFFSET).max(0);
+    let filter = AuditLogFilter {
+        event: params.event,
+        actor: params.actor,
+        target: params.target,
+        from: params.from,
+        to: params.to,
+    };
+
+    match find_audit_logs(&filter, limit, offset, &pool).await {
+        Ok(audit_logs) => {
+            let response = serde_json::to_string(&GetAuditLogsResult {
+                audit_logs: audit_logs.into_iter().map(AuditLogResponse::from).collect(),
+                limit,
+                offset,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[audit][get_audit_logs] error on getting audit logs with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting audit logs please try again",
+            )
+            .into_response()
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use chrono::NaiveDateTime;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgQueryResult};
+
+use crate::utils::generate_random_str;
+
+pub mod api;
+mod test;
+
+const AUDIT_LOG_ID_LENGTH: u8 = 16;
+// AUDIT_LOGS.USER_AGENT is unbounded, but there is no point keeping huge headers
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    SignUp,
+    LoginSucceeded,
+    LoginFailed,
+    TokenRefreshed,
+    SessionRevoked,
+    PermissionDenied,
+    RoadmapDeleted,
+    RoadmapOwnershipTransferred,
+}
+
+impl AuditEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::SignUp => "sign_up",
+            AuditEvent::LoginSucceeded => "login_succeeded",
+            AuditEvent::LoginFailed => "login_failed",
+            AuditEvent::TokenRefreshed => "token_refreshed",
+            AuditEvent::SessionRevoked => "session_revoked",
+            AuditEvent::PermissionDenied => "permission_denied",
+            AuditEvent::RoadmapDeleted => "roadmap_deleted",
+            AuditEvent::RoadmapOwnershipTransferred => "roadmap_ownership_transferred",
+        }
+    }
+}
+
+/// Where a request came from, recorded alongside every audit event.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl RequestContext {
+    pub fn from_parts(parts: &Parts) -> RequestContext {
+        // only available when served with `into_make_service_with_connect_info`
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        RequestContext {
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestContext::from_parts(parts))
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    pub id: String,
+    pub event: String,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+    pub event: Option<AuditEvent>,
+    pub actor: Option<String>,
+    pub target: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+pub async fn add_audit_log(
+    event: AuditEvent,
+    actor: Option<&str>,
+    target: Option<&str>,
+    details: Option<&str>,
+    context: &RequestContext,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO AUDIT_LOGS
+                (ID, EVENT, ACTOR, TARGET, IP_ADDRESS, USER_AGENT, DETAILS)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        generate_random_str(AUDIT_LOG_ID_LENGTH),
+        event.as_str(),
+        actor,
+        target,
+        context.ip_address,
+        context.user_agent,
+        details
+    )
+    .execute(pool)
+    .await
+}
+
+/// Same as `add_audit_log` but only logs failures, auditing should never fail the request.
+pub async fn record_audit_event(
+    event: AuditEvent,
+    actor: Option<&str>,
+    target: Option<&str>,
+    details: Option<&str>,
+    context: &RequestContext,
+    pool: &PgPool,
+) {
+    if let Err(err) = add_audit_log(event, actor, target, details, context, pool).await {
+        error!(
+            "[audit][record_audit_event] error on recording {} of {:?} with cause {}",
+            event.as_str(),
+            actor,
+            err
+        );
+    }
+}
+
+pub async fn find_audit_logs(
+    filter: &AuditLogFilter,
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> Result<Vec<AuditLog>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditLog,
+        r#"
+            SELECT ID, EVENT, ACTOR, TARGET, IP_ADDRESS, USER_AGENT, DETAILS, CREATED_AT
+            FROM AUDIT_LOGS
+            WHERE
+                ($1::VARCHAR IS NULL OR EVENT = $1) AND
+                ($2::VARCHAR IS NULL OR ACTOR = $2) AND
+                ($3::VARCHAR IS NULL OR TARGET = $3) AND
+                ($4::TIMESTAMP IS NULL OR CREATED_AT >= $4) AND
+                ($5::TIMESTAMP IS NULL OR CREATED_AT < $5)
+            ORDER BY CREATED_AT DESC, ID
+            LIMIT $6
+            OFFSET $7
+        "#,
+        filter.event.map(|event| event.as_str()),
+        filter.actor,
+        filter.target,
+        filter.from,
+        filter.to,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
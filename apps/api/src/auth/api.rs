@@ -1,19 +1,37 @@
-use std::collections::HashMap;
-
-use crate::router_common::{CreateResponse, RouterGlobalState};
+use crate::{
+    audit::{record_audit_event, AuditEvent, RequestContext},
+    router_common::{CreateResponse, RouterGlobalState},
+    utils::parse_cookie_value,
+};
 use axum::{
     body::Body,
-    extract::State,
+    extract::{Path, Query, State},
     http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
     Json,
 };
+use chrono::{Local, NaiveDateTime, TimeDelta};
 use log::error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::{
-    create_user_session, login, refresh_user_session, sign_up, User, DEFAULT_SESSION_DURATION_MIN,
+    create_user_session,
+    extractor::AuthenticatedUser,
+    login,
+    oidc::{begin_oidc_login, complete_oidc_login, LOGIN_REQUEST_DURATION_MIN},
+    refresh_user_session, sign_up,
+    token::{
+        create_personal_access_token, get_user_personal_access_tokens,
+        revoke_personal_access_token, PersonalAccessToken, TokenScope,
+    },
+    User, UserSession, DEFAULT_SESSION_DURATION_MIN,
 };
 
+// same as PERSONAL_ACCESS_TOKENS.NAME column length
+const MAX_TOKEN_NAME_LENGTH: usize = 64;
+const MAX_TOKEN_EXPIRY_DAYS: i64 = 365;
+const OIDC_STATE_COOKIE: &str = "oidc_state";
+
 #[derive(Deserialize)]
 pub struct LoginRequest {
     username: String,
@@ -28,30 +46,89 @@ pub struct SignUpRequest {
     name: String,
 }
 
-fn parse_cookie_value(value: String) -> HashMap<String, String> {
-    let separated_kv = value.split(";");
-    let mut map = HashMap::new();
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    scopes: Vec<TokenScope>,
+    // token never expires when not set
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PersonalAccessTokenResponse {
+    id: String,
+    name: String,
+    scopes: Vec<String>,
+    expiry_date: Option<NaiveDateTime>,
+    last_used_at: Option<NaiveDateTime>,
+    created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CreateTokenResponse {
+    is_successful: bool,
+    message: String,
+    // only returned once, the server doesn't keep the plain text token
+    token: String,
+    personal_access_token: PersonalAccessTokenResponse,
+}
 
-    for unstructured_kv in separated_kv {
-        let mut kv = unstructured_kv.split("=");
-        let key = kv.next();
-        let value = kv.next();
+#[derive(Deserialize, Serialize)]
+pub struct PersonalAccessTokensResponse {
+    personal_access_tokens: Vec<PersonalAccessTokenResponse>,
+}
 
-        if key.is_some() && value.is_some() {
-            map.insert(String::from(key.unwrap()), String::from(value.unwrap()));
+impl From<PersonalAccessToken> for PersonalAccessTokenResponse {
+    fn from(personal_access_token: PersonalAccessToken) -> Self {
+        PersonalAccessTokenResponse {
+            id: personal_access_token.id,
+            name: personal_access_token.name,
+            scopes: personal_access_token.scopes,
+            expiry_date: personal_access_token.expiry_date,
+            last_used_at: personal_access_token.last_used_at,
+            created_at: personal_access_token.created_at,
         }
     }
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+fn session_cookies(session: &UserSession) -> (String, String) {
+    let access_token_cookie = format!(
+        "token={}; HttpOnly; Secure; Path=/; Max-Age={}",
+        &session.token,
+        DEFAULT_SESSION_DURATION_MIN * 60
+    );
+    let refresh_token_cookie = format!(
+        "refresh_token={}; HttpOnly; Secure; Path=/refresh_token",
+        &session.refresh_token
+    );
 
-    return map;
+    (access_token_cookie, refresh_token_cookie)
+}
+
+// ties the login to the browser that started it, Lax so it is sent on the provider redirect
+fn oidc_state_cookie(state: &str, max_age_secs: i64) -> String {
+    format!(
+        "{}={}; HttpOnly; Secure; SameSite=Lax; Path=/api/v1/oidc; Max-Age={}",
+        OIDC_STATE_COOKIE, state, max_age_secs
+    )
 }
 
 pub async fn login_router(
     State(router_global_state): State<RouterGlobalState>,
+    context: RequestContext,
     Json(request): Json<LoginRequest>,
 ) -> Response<Body> {
     let result = login(
         &request.username,
         &request.password,
+        &router_global_state.config.password_hash,
         &router_global_state.pool,
     )
     .await;
@@ -77,15 +154,16 @@ pub async fn login_router(
 
             match session_result {
                 Ok(session) => {
-                    let access_token_cookie = format!(
-                        "token={}; HttpOnly; Secure; Path=/; Max-Age={}",
-                        &session.token,
-                        DEFAULT_SESSION_DURATION_MIN * 60
-                    );
-                    let refresh_token_cookie = format!(
-                        "refresh_token={}; HttpOnly; Secure; Path=/refresh_token",
-                        &session.refresh_token
-                    );
+                    record_audit_event(
+                        AuditEvent::LoginSucceeded,
+                        Some(&request.username),
+                        None,
+                        Some("password"),
+                        &context,
+                        &router_global_state.pool,
+                    )
+                    .await;
+                    let (access_token_cookie, refresh_token_cookie) = session_cookies(&session);
 
                     let response = serde_json::to_string(&CreateResponse {
                         is_successful: true,
@@ -103,6 +181,15 @@ pub async fn login_router(
                 }
                 Err(err_msg) => {
                     error!("{}", err_msg);
+                    record_audit_event(
+                        AuditEvent::LoginFailed,
+                        Some(&request.username),
+                        None,
+                        Some(&err_msg),
+                        &context,
+                        &router_global_state.pool,
+                    )
+                    .await;
                     let response = serde_json::to_string(&CreateResponse {
                         is_successful: false,
                         message: String::from("Invalid username or password"),
@@ -119,6 +206,15 @@ pub async fn login_router(
         }
         Err(err) => {
             error!("[auth][login] Error on signing in with cause {}", err);
+            record_audit_event(
+                AuditEvent::LoginFailed,
+                Some(&request.username),
+                None,
+                Some(&err),
+                &context,
+                &router_global_state.pool,
+            )
+            .await;
             let response = serde_json::to_string(&CreateResponse {
                 is_successful: false,
                 message: String::from("Invalid username or password"),
@@ -135,7 +231,8 @@ pub async fn login_router(
 }
 
 pub async fn sign_up_router(
-    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    State(RouterGlobalState { pool, config, .. }): State<RouterGlobalState>,
+    context: RequestContext,
     Json(request): Json<SignUpRequest>,
 ) -> Response<Body> {
     let mut sign_up_user = User {
@@ -147,7 +244,7 @@ pub async fn sign_up_router(
         name: request.name,
     };
 
-    let sign_up_result = sign_up(&mut sign_up_user, &pool).await;
+    let sign_up_result = sign_up(&mut sign_up_user, &config.password_hash, &pool).await;
 
     match sign_up_result {
         Ok(is_successful) => {
@@ -165,6 +262,16 @@ pub async fn sign_up_router(
                     .unwrap();
             }
 
+            record_audit_event(
+                AuditEvent::SignUp,
+                Some(&request.username),
+                None,
+                None,
+                &context,
+                &pool,
+            )
+            .await;
+
             let response = serde_json::to_string(&CreateResponse {
                 is_successful: true,
                 message: String::from("Successfuly sign up"),
@@ -197,6 +304,7 @@ pub async fn sign_up_router(
 
 pub async fn refresh_token_router(
     State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    context: RequestContext,
     headers: HeaderMap,
 ) -> Response<Body> {
     let cookies = headers.get("cookie");
@@ -222,6 +330,16 @@ pub async fn refresh_token_router(
             let new_user_session = refresh_user_session(&refresh_token.unwrap(), &pool).await;
             match new_user_session {
                 Ok(user_session) => {
+                    record_audit_event(
+                        AuditEvent::TokenRefreshed,
+                        Some(&user_session.username),
+                        None,
+                        None,
+                        &context,
+                        &pool,
+                    )
+                    .await;
+
                     let response = serde_json::to_string(&CreateResponse {
                         is_successful: true,
                         message: String::from("Token refreshed"),
@@ -266,3 +384,313 @@ pub async fn refresh_token_router(
         }
     }
 }
+
+pub async fn oidc_login_router(
+    State(RouterGlobalState { pool, config, .. }): State<RouterGlobalState>,
+) -> Response<Body> {
+    let oidc_config = match &config.oidc {
+        Some(oidc_config) => oidc_config,
+        None => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Single sign on is not enabled"),
+                id: None,
+            })
+            .unwrap();
+
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(response))
+                .unwrap();
+        }
+    };
+
+    match begin_oidc_login(oidc_config, &pool).await {
+        Ok((authorization_url, state)) => Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(header::LOCATION, authorization_url)
+            .header(
+                header::SET_COOKIE,
+                oidc_state_cookie(&state, LOGIN_REQUEST_DURATION_MIN * 60),
+            )
+            .body(Body::empty())
+            .unwrap(),
+        Err(err) => {
+            error!("[auth][oidc_login_router] Failed to start login due to {err}");
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Could not reach identity provider, please try again"),
+                id: None,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::from(response))
+                .unwrap()
+        }
+    }
+}
+
+pub async fn oidc_callback_router(
+    State(RouterGlobalState { pool, config, .. }): State<RouterGlobalState>,
+    context: RequestContext,
+    headers: HeaderMap,
+    Query(params): Query<OidcCallbackParams>,
+) -> Response<Body> {
+    let failed_response = || {
+        let response = serde_json::to_string(&CreateResponse {
+            is_successful: false,
+            message: String::from("Could not sign in with identity provider, please try again"),
+            id: None,
+        })
+        .unwrap();
+
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::SET_COOKIE, oidc_state_cookie("", 0))
+            .body(Body::from(response))
+            .unwrap()
+    };
+    let browser_state = headers
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_cookie_value(value.to_owned()).remove(OIDC_STATE_COOKIE));
+
+    let (oidc_config, code, state) = match (&config.oidc, params.code, params.state) {
+        (Some(oidc_config), Some(code), Some(state)) => (oidc_config, code, state),
+        _ => {
+            if let Some(err) = params.error {
+                error!("[auth][oidc_callback_router] Identity provider returned {err}");
+            }
+            return failed_response();
+        }
+    };
+
+    let username = match complete_oidc_login(
+        oidc_config,
+        &code,
+        &state,
+        browser_state.as_ref(),
+        &pool,
+    )
+    .await
+    {
+        Ok(username) => username,
+        Err(err) => {
+            error!("[auth][oidc_callback_router] Failed to complete login due to {err}");
+            record_audit_event(
+                AuditEvent::LoginFailed,
+                None,
+                None,
+                Some(&err),
+                &context,
+                &pool,
+            )
+            .await;
+            return failed_response();
+        }
+    };
+
+    match create_user_session(&username, &pool).await {
+        Ok(session) => {
+            record_audit_event(
+                AuditEvent::LoginSucceeded,
+                Some(&username),
+                None,
+                Some(&oidc_config.provider),
+                &context,
+                &pool,
+            )
+            .await;
+            let (access_token_cookie, refresh_token_cookie) = session_cookies(&session);
+
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Successfully login"),
+                id: Some(username),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::SET_COOKIE, access_token_cookie)
+                .header(header::SET_COOKIE, refresh_token_cookie)
+                .header(header::SET_COOKIE, oidc_state_cookie("", 0))
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!("[auth][oidc_callback_router] Failed to create session due to {err}");
+            failed_response()
+        }
+    }
+}
+
+pub async fn create_token_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Json(request): Json<CreateTokenRequest>,
+) -> Response<Body> {
+    if let Err(err) = user.require_session() {
+        return err.into_response();
+    }
+
+    let name = request.name.trim().to_string();
+    // outer None when the expiry is out of range, inner None when the token never expires
+    let expiry_date = match request.expires_in_days {
+        None => Some(None),
+        Some(days) if days <= 0 || days > MAX_TOKEN_EXPIRY_DAYS => None,
+        Some(days) => TimeDelta::try_days(days)
+            .and_then(|duration| Local::now().checked_add_signed(duration))
+            .map(|expiry_date| Some(expiry_date.naive_utc())),
+    };
+
+    if name.is_empty()
+        || name.chars().count() > MAX_TOKEN_NAME_LENGTH
+        || request.scopes.is_empty()
+        || expiry_date.is_none()
+    {
+        let response = serde_json::to_string(&CreateResponse {
+            is_successful: false,
+            message: format!(
+                "Token needs a name, at least one scope and an expiry between 1 and {} days",
+                MAX_TOKEN_EXPIRY_DAYS
+            ),
+            id: None,
+        })
+        .unwrap();
+
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::from(response))
+            .unwrap();
+    }
+    let expiry_date = expiry_date.flatten();
+
+    match create_personal_access_token(&user.username, &name, &request.scopes, expiry_date, &pool)
+        .await
+    {
+        Ok((personal_access_token, token)) => {
+            let response = serde_json::to_string(&CreateTokenResponse {
+                is_successful: true,
+                message: String::from("Token created, make sure to copy it now"),
+                token,
+                personal_access_token: personal_access_token.into(),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!("[auth][create_token_router] Failed to create token due to {err}");
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Could not create token, please try again"),
+                id: None,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(response))
+                .unwrap()
+        }
+    }
+}
+
+pub async fn get_tokens_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+) -> Response<Body> {
+    if let Err(err) = user.require_session() {
+        return err.into_response();
+    }
+
+    match get_user_personal_access_tokens(&user.username, &pool).await {
+        Ok(personal_access_tokens) => {
+            let response = serde_json::to_string(&PersonalAccessTokensResponse {
+                personal_access_tokens: personal_access_tokens
+                    .into_iter()
+                    .map(PersonalAccessTokenResponse::from)
+                    .collect(),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!("[auth][get_tokens_router] Failed to get tokens due to {err}");
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Could not get tokens, please try again"),
+                id: None,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(response))
+                .unwrap()
+        }
+    }
+}
+
+pub async fn revoke_token_router(
+    State(RouterGlobalState { pool, .. }): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(token_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) = user.require_session() {
+        return err.into_response();
+    }
+
+    match revoke_personal_access_token(&token_id, &user.username, &pool).await {
+        Ok(result) if result.rows_affected() == 1 => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Token revoked"),
+                id: Some(token_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Ok(_) => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Token not found"),
+                id: None,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!("[auth][revoke_token_router] Failed to revoke token due to {err}");
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: false,
+                message: String::from("Could not revoke token, please try again"),
+                id: None,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(response))
+                .unwrap()
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::marker::PhantomData;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, StatusCode},
+};
+use log::{error, info};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_event, AuditEvent, RequestContext},
+    router_common::{ApiError, RouterGlobalState},
+    utils::parse_cookie_value,
+};
+
+use super::{
+    find_session_by_token,
+    role::{find_user_access, Permission, Role},
+    token::{authenticate_personal_access_token, TokenScope},
+    validate_session,
+};
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// The user making the request, authenticated either with the session cookie set by
+/// `login_router` or with a personal access token sent as `Authorization: Bearer <token>`.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub role: Role,
+    pub scopes: Vec<TokenScope>,
+    // only set when authenticated with a personal access token
+    pub personal_access_token_id: Option<String>,
+    // only set when authenticated with the session cookie, long lived connections check it again
+    pub session_token: Option<String>,
+    // where the request came from, kept to audit what the user gets denied
+    pub context: RequestContext,
+}
+
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+pub struct CanViewUsers;
+pub struct CanManageUsers;
+pub struct CanRevokeSessions;
+pub struct CanModerateRoadmaps;
+pub struct CanViewAuditLogs;
+
+impl RequiredPermission for CanViewUsers {
+    const PERMISSION: Permission = Permission::ViewUsers;
+}
+
+impl RequiredPermission for CanManageUsers {
+    const PERMISSION: Permission = Permission::ManageUsers;
+}
+
+impl RequiredPermission for CanRevokeSessions {
+    const PERMISSION: Permission = Permission::RevokeSessions;
+}
+
+impl RequiredPermission for CanModerateRoadmaps {
+    const PERMISSION: Permission = Permission::ModerateRoadmaps;
+}
+
+impl RequiredPermission for CanViewAuditLogs {
+    const PERMISSION: Permission = Permission::ViewAuditLogs;
+}
+
+/// Authenticated user whose role grants the permission `P`, e.g. `Authorized<CanManageUsers>`.
+/// Personal access tokens are rejected since none of their scopes cover administration.
+pub struct Authorized<P: RequiredPermission> {
+    pub user: AuthenticatedUser,
+    permission: PhantomData<P>,
+}
+
+impl AuthenticatedUser {
+    pub fn has_scope(&self, scope: TokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Audits that the user was denied `details` on `target`.
+    pub async fn record_denial(&self, target: Option<&str>, details: &str, pool: &PgPool) {
+        info!(
+            "[auth][extractor] {} was denied {} on {}",
+            self.username,
+            details,
+            target.unwrap_or("-")
+        );
+        record_audit_event(
+            AuditEvent::PermissionDenied,
+            Some(&self.username),
+            target,
+            Some(details),
+            &self.context,
+            pool,
+        )
+        .await;
+    }
+
+    pub async fn require_scope(&self, scope: TokenScope, pool: &PgPool) -> Result<(), ApiError> {
+        if self.has_scope(scope) {
+            return Ok(());
+        }
+
+        self.record_denial(None, &format!("{} scope", scope.as_str()), pool)
+            .await;
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            &format!("Token is missing the {} scope", scope.as_str()),
+        ))
+    }
+
+    // personal access tokens should not be able to manage other tokens or the account
+    pub fn require_session(&self) -> Result<(), ApiError> {
+        if self.personal_access_token_id.is_none() {
+            return Ok(());
+        }
+
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Please login to perform this action",
+        ))
+    }
+}
+
+async fn authenticate_username(
+    parts: &Parts,
+    state: &RouterGlobalState,
+) -> Result<(String, Vec<TokenScope>, Option<String>, Option<String>), ApiError> {
+    let bearer_token = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(BEARER_PREFIX));
+
+    if let Some(token) = bearer_token {
+        return match authenticate_personal_access_token(token, &state.pool).await {
+            Ok(Some(personal_access_token)) => Ok((
+                personal_access_token.username.clone(),
+                personal_access_token.token_scopes(),
+                Some(personal_access_token.id),
+                None,
+            )),
+            Ok(None) => Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "Invalid or expired token",
+            )),
+            Err(err) => {
+                error!("[auth][extractor] Failed to authenticate token due to {err}");
+                Err(ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Could not authenticate, please try again",
+                ))
+            }
+        };
+    }
+
+    let session_token = parts
+        .headers
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_cookie_value(value.to_owned()).remove("token"));
+
+    let session_token = match session_token {
+        Some(session_token) => session_token,
+        None => {
+            return Err(ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "Please login before continuing",
+            ))
+        }
+    };
+
+    match find_session_by_token(&session_token, &state.pool).await {
+        Ok(session) if session.expiry_date.is_some() => {
+            if !validate_session(&session).await.unwrap_or(false) {
+                return Err(ApiError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "Session expired, please refresh your token",
+                ));
+            }
+
+            Ok((
+                session.username,
+                TokenScope::ALL.to_vec(),
+                None,
+                Some(session_token),
+            ))
+        }
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "Please login before continuing",
+        )),
+        Err(err) => {
+            error!("[auth][extractor] Failed to find session due to {err}");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not authenticate, please try again",
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<RouterGlobalState> for AuthenticatedUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &RouterGlobalState,
+    ) -> Result<Self, Self::Rejection> {
+        let (username, scopes, personal_access_token_id, session_token) =
+            authenticate_username(parts, state).await?;
+
+        // checked on every request so disabling an account takes effect immediately
+        match find_user_access(&username, &state.pool).await {
+            Ok(access) if access.disabled_at.is_some() => {
+                Err(ApiError::new(StatusCode::FORBIDDEN, "Account is disabled"))
+            }
+            Ok(access) => Ok(AuthenticatedUser {
+                username,
+                role: access.role,
+                scopes,
+                personal_access_token_id,
+                session_token,
+                context: RequestContext::from_parts(parts),
+            }),
+            Err(err) => {
+                error!("[auth][extractor] Failed to find user {username} due to {err}");
+                Err(ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Could not authenticate, please try again",
+                ))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: RequiredPermission> FromRequestParts<RouterGlobalState> for Authorized<P> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &RouterGlobalState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+        user.require_session()?;
+
+        if !user.role.has_permission(P::PERMISSION) {
+            user.record_denial(
+                Some(parts.uri.path()),
+                &format!("{:?}", P::PERMISSION),
+                &state.pool,
+            )
+            .await;
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "You don't have permission to perform this action",
+            ));
+        }
+
+        Ok(Authorized {
+            user,
+            permission: PhantomData,
+        })
+    }
+}
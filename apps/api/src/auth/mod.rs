@@ -1,9 +1,5 @@
 use std::cmp::Ordering;
 
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2, PasswordHash, PasswordVerifier,
-};
 use chrono::{Duration, NaiveDateTime};
 use log::error;
 use sqlx::{
@@ -11,10 +7,17 @@ use sqlx::{
     types::chrono::Local,
 };
 
-use crate::utils::generate_random_str;
+use crate::{config::PasswordHashConfig, utils::generate_random_str};
+use password::{hash_password, verify_password};
+use role::find_user_access;
 
 pub mod api;
+pub mod extractor;
+pub mod oidc;
+pub mod password;
+pub mod role;
 mod test;
+pub mod token;
 
 // TODO: add config so can be defined when running the application
 const DEFAULT_TOKEN_LENGTH: u8 = 128;
@@ -41,8 +44,8 @@ impl PartialEq for User {
 
 #[derive(PartialEq, Debug)]
 pub struct UserSession {
-    username: String,
-    token: String,
+    pub username: String,
+    pub token: String,
     refresh_token: String,
     expiry_date: Option<NaiveDateTime>,
     created_at: Option<NaiveDateTime>,
@@ -64,9 +67,13 @@ pub async fn add_user(user: &User, pool: &PgPool) -> Result<PgQueryResult, sqlx:
 }
 
 pub async fn find_user(username: &String, pool: &PgPool) -> Result<User, sqlx::Error> {
-    let user = sqlx::query_as!(User, "SELECT * FROM USERS WHERE USERNAME = $1", username)
-        .fetch_one(pool)
-        .await;
+    let user = sqlx::query_as!(
+        User,
+        "SELECT USERNAME, EMAIL, PASSWORD, NAME, CREATED_AT, UPDATED_AT FROM USERS WHERE USERNAME = $1",
+        username
+    )
+    .fetch_one(pool)
+    .await;
 
     user
 }
@@ -99,17 +106,58 @@ pub async fn find_session_by_username(
     query
 }
 
+pub async fn find_session_by_token(
+    token: &String,
+    pool: &PgPool,
+) -> Result<UserSession, sqlx::Error> {
+    sqlx::query_as!(
+        UserSession,
+        "SELECT * FROM USERS_SESSION WHERE TOKEN = $1",
+        token
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Whether a session with the token exists and hasn't expired.
+pub async fn is_session_active(token: &String, pool: &PgPool) -> Result<bool, sqlx::Error> {
+    match find_session_by_token(token, pool).await {
+        Ok(session) if session.expiry_date.is_some() => {
+            Ok(validate_session(&session).await.unwrap_or(false))
+        }
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
 //TODO: Add logger for knowing what happen to the code
-pub async fn sign_up(user: &mut User, pool: &PgPool) -> Result<bool, String> {
-    let password = user.password.as_bytes();
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+pub async fn update_user_password(
+    username: &String,
+    old_password: &String,
+    new_password: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    // only replaces the hash that was verified, in case the password changed in the meantime
+    sqlx::query!(
+        "UPDATE USERS SET PASSWORD = $1, UPDATED_AT = NOW() WHERE USERNAME = $2 AND PASSWORD = $3",
+        new_password,
+        username,
+        old_password
+    )
+    .execute(pool)
+    .await
+}
 
-    let password_hash = argon2.hash_password(password, &salt);
+pub async fn sign_up(
+    user: &mut User,
+    password_config: &PasswordHashConfig,
+    pool: &PgPool,
+) -> Result<bool, String> {
+    let password_hash = hash_password(&user.password, password_config);
 
     match password_hash {
         Ok(hashhed_password) => {
-            user.password = hashhed_password.to_string();
+            user.password = hashhed_password;
             let result = add_user(&user, pool).await;
 
             match result {
@@ -117,26 +165,43 @@ pub async fn sign_up(user: &mut User, pool: &PgPool) -> Result<bool, String> {
                 Err(err) => Err(err.to_string()),
             }
         }
-        Err(err) => Err(err.to_string()),
+        Err(err) => Err(err),
     }
 }
 
-pub async fn login(username: &String, password: &String, pool: &PgPool) -> Result<bool, String> {
+pub async fn login(
+    username: &String,
+    password: &str,
+    password_config: &PasswordHashConfig,
+    pool: &PgPool,
+) -> Result<bool, String> {
     let find_result = find_user(&username, pool).await;
 
     match find_result {
         Ok(user) => {
-            let hash = PasswordHash::new(&user.password);
-            match hash {
-                Ok(hash_value) => {
-                    let argon = Argon2::default();
-                    match argon.verify_password(password.as_bytes(), &hash_value) {
-                        Ok(_) => Ok(true),
-                        Err(err_message) => Err(err_message.to_string()),
+            let needs_rehash = verify_password(password, &user.password, password_config)?;
+
+            // the password is known at this point, so outdated hashes are upgraded transparently
+            if needs_rehash {
+                match hash_password(password, password_config) {
+                    Ok(new_hash) => {
+                        if let Err(err) =
+                            update_user_password(username, &user.password, &new_hash, pool).await
+                        {
+                            error!(
+                                "[auth][login] error on rehashing password of {} with cause {}",
+                                username, err
+                            );
+                        }
                     }
+                    Err(err) => error!(
+                        "[auth][login] error on rehashing password of {} with cause {}",
+                        username, err
+                    ),
                 }
-                Err(err) => Err(err.to_string()),
             }
+
+            Ok(true)
         }
         Err(err) => Err(err.to_string()),
     }
@@ -186,6 +251,14 @@ pub async fn create_user_session(username: &String, pool: &PgPool) -> Result<Use
     let refresh_token = generate_random_str(DEFAULT_TOKEN_LENGTH);
     let current_time = Local::now();
 
+    match find_user_access(username, pool).await {
+        Ok(access) if access.disabled_at.is_some() => {
+            return Err(String::from("Account is disabled"));
+        }
+        Ok(_) => {}
+        Err(err) => return Err(err.to_string()),
+    }
+
     let existing_session = find_session_by_username(username, pool).await;
 
     if existing_session.is_ok() {
@@ -228,7 +301,7 @@ pub async fn validate_session(user_session: &UserSession) -> Result<bool, String
     match user_session.expiry_date {
         Some(expiry_date) => {
             let current_time = Local::now().naive_utc();
-            Ok(current_time.cmp(&expiry_date) == Ordering::Greater)
+            Ok(current_time.cmp(&expiry_date) == Ordering::Less)
         }
         None => {
             panic!("Expiry date should not have been none")
@@ -0,0 +1,371 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, NaiveDateTime};
+use log::error;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgQueryResult, types::chrono::Local, PgPool};
+
+use crate::{config::OidcConfig, utils::generate_random_str};
+
+use super::find_user;
+
+const STATE_LENGTH: u8 = 32;
+// RFC 7636 requires the verifier to be between 43 and 128 characters long
+const CODE_VERIFIER_LENGTH: u8 = 64;
+pub const LOGIN_REQUEST_DURATION_MIN: i64 = 10;
+// same as USERS.USERNAME and USERS.NAME column length
+const MAX_USERNAME_LENGTH: usize = 32;
+const MAX_NAME_LENGTH: usize = 32;
+const USERNAME_SUFFIX_LENGTH: u8 = 6;
+const FALLBACK_USERNAME: &str = "user";
+
+#[derive(Deserialize, Debug)]
+pub struct ProviderMetadata {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserInfoClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+}
+
+struct LoginRequest {
+    code_verifier: String,
+    expiry_date: NaiveDateTime,
+}
+
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub async fn discover_provider(config: &OidcConfig) -> Result<ProviderMetadata, String> {
+    let discovery_url = format!(
+        "{}/.well-known/openid-configuration",
+        config.issuer_url.trim_end_matches('/')
+    );
+
+    let response = reqwest::get(discovery_url)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Provider discovery failed with status {}",
+            response.status()
+        ));
+    }
+
+    response
+        .json::<ProviderMetadata>()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn add_login_request(
+    state: &String,
+    code_verifier: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    let expiry_date = Local::now() + Duration::minutes(LOGIN_REQUEST_DURATION_MIN);
+
+    sqlx::query!(
+        "INSERT INTO OIDC_LOGIN_REQUESTS (STATE, CODE_VERIFIER, EXPIRY_DATE) VALUES ($1, $2, $3)",
+        state,
+        code_verifier,
+        expiry_date.naive_utc()
+    )
+    .execute(pool)
+    .await
+}
+
+// requests of logins that were abandoned are never taken, they are cleared as new ones come in
+async fn delete_expired_login_requests(pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM OIDC_LOGIN_REQUESTS WHERE EXPIRY_DATE < $1",
+        Local::now().naive_utc()
+    )
+    .execute(pool)
+    .await
+}
+
+// the request is deleted on read so a state can only ever be redeemed once
+async fn take_login_request(
+    state: &String,
+    pool: &PgPool,
+) -> Result<Option<LoginRequest>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginRequest,
+        r#"
+            DELETE FROM OIDC_LOGIN_REQUESTS
+            WHERE STATE = $1
+            RETURNING CODE_VERIFIER, EXPIRY_DATE
+        "#,
+        state
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns the username linked to the external identity, if any.
+pub async fn find_identity(
+    provider: &String,
+    subject: &String,
+    pool: &PgPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let query = sqlx::query!(
+        "SELECT USERNAME FROM USER_IDENTITIES WHERE PROVIDER = $1 AND SUBJECT = $2",
+        provider,
+        subject
+    )
+    .fetch_optional(pool)
+    .await;
+
+    query.map(|record| record.map(|identity| identity.username))
+}
+
+/// Builds the authorization URL the user should be redirected to, the PKCE verifier is kept on
+/// the server and only its challenge is sent to the provider. Returns the URL with the state,
+/// which the browser has to keep to complete the login.
+pub async fn begin_oidc_login(
+    config: &OidcConfig,
+    pool: &PgPool,
+) -> Result<(String, String), String> {
+    let provider_metadata = discover_provider(config).await?;
+
+    let state = generate_random_str(STATE_LENGTH);
+    let code_verifier = generate_random_str(CODE_VERIFIER_LENGTH);
+
+    if let Err(err) = delete_expired_login_requests(pool).await {
+        error!(
+            "[auth][begin_oidc_login] failed deleting expired login requests with cause {}",
+            err
+        );
+    }
+
+    if let Err(err) = add_login_request(&state, &code_verifier, pool).await {
+        return Err(err.to_string());
+    }
+
+    let authorization_url = Url::parse_with_params(
+        &provider_metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_url.as_str()),
+            ("scope", config.scopes.as_str()),
+            ("state", state.as_str()),
+            ("code_challenge", code_challenge(&code_verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|err| err.to_string())?;
+
+    Ok((authorization_url.to_string(), state))
+}
+
+async fn exchange_code(
+    config: &OidcConfig,
+    provider_metadata: &ProviderMetadata,
+    code: &str,
+    code_verifier: &str,
+) -> Result<String, String> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+
+    if let Some(client_secret) = &config.client_secret {
+        form.push(("client_secret", client_secret.as_str()));
+    }
+
+    let response = reqwest::Client::new()
+        .post(&provider_metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Token exchange failed with status {}",
+            response.status()
+        ));
+    }
+
+    match response.json::<TokenResponse>().await {
+        Ok(token_response) => Ok(token_response.access_token),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+async fn fetch_user_info(
+    provider_metadata: &ProviderMetadata,
+    access_token: &str,
+) -> Result<UserInfoClaims, String> {
+    let response = reqwest::Client::new()
+        .get(&provider_metadata.userinfo_endpoint)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Fetching user info failed with status {}",
+            response.status()
+        ));
+    }
+
+    response
+        .json::<UserInfoClaims>()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+fn base_username(claims: &UserInfoClaims) -> String {
+    let email_name = claims
+        .email
+        .as_ref()
+        .and_then(|email| email.split('@').next().map(String::from));
+
+    let username: String = claims
+        .preferred_username
+        .clone()
+        .or(email_name)
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-' || *c == '.')
+        // leave some room for the suffix in case the username is already taken
+        .take(MAX_USERNAME_LENGTH - USERNAME_SUFFIX_LENGTH as usize - 1)
+        .collect();
+
+    if username.is_empty() {
+        return FALLBACK_USERNAME.to_string();
+    }
+
+    username
+}
+
+async fn available_username(claims: &UserInfoClaims, pool: &PgPool) -> String {
+    let username = base_username(claims);
+
+    match find_user(&username, pool).await {
+        Err(sqlx::Error::RowNotFound) => username,
+        _ => format!(
+            "{}_{}",
+            username,
+            generate_random_str(USERNAME_SUFFIX_LENGTH)
+        ),
+    }
+}
+
+// NOTE: provisioned accounts don't have a local password, an empty hash never parses so password
+// login is always rejected for them
+async fn provision_user(
+    provider: &String,
+    claims: &UserInfoClaims,
+    pool: &PgPool,
+) -> Result<String, sqlx::Error> {
+    let username = available_username(claims, pool).await;
+    let name: String = claims
+        .name
+        .clone()
+        .unwrap_or(username.clone())
+        .chars()
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    let email = claims.email.clone().unwrap_or_default();
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO USERS (USERNAME, EMAIL, PASSWORD, NAME) VALUES ($1, $2, '', $3)",
+        username,
+        email,
+        name
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO USER_IDENTITIES
+                (PROVIDER, SUBJECT, USERNAME, EMAIL)
+            VALUES
+                ($1, $2, $3, $4)
+        "#,
+        provider,
+        claims.sub,
+        username,
+        claims.email
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(username)
+}
+
+/// Redeems the authorization code returned to the callback and returns the username of the
+/// linked account, creating one the first time the identity signs in. `browser_state` is the
+/// state kept by the browser calling back, so a login started elsewhere can't be completed in it.
+pub async fn complete_oidc_login(
+    config: &OidcConfig,
+    code: &str,
+    state: &String,
+    browser_state: Option<&String>,
+    pool: &PgPool,
+) -> Result<String, String> {
+    if browser_state != Some(state) {
+        return Err(String::from("Login state wasn't started by this browser"));
+    }
+
+    let login_request = match take_login_request(state, pool).await {
+        Ok(Some(login_request)) => login_request,
+        Ok(None) => return Err(String::from("Unknown login state")),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    if login_request.expiry_date < Local::now().naive_utc() {
+        return Err(String::from("Login request expired"));
+    }
+
+    let provider_metadata = discover_provider(config).await?;
+    let access_token = exchange_code(
+        config,
+        &provider_metadata,
+        code,
+        &login_request.code_verifier,
+    )
+    .await?;
+    let claims = fetch_user_info(&provider_metadata, &access_token).await?;
+
+    match find_identity(&config.provider, &claims.sub, pool).await {
+        Ok(Some(username)) => Ok(username),
+        Ok(None) => provision_user(&config.provider, &claims, pool)
+            .await
+            .map_err(|err| {
+                error!(
+                    "[auth][complete_oidc_login] failed provisioning user for {} with cause {}",
+                    claims.sub, err
+                );
+                err.to_string()
+            }),
+        Err(err) => Err(err.to_string()),
+    }
+}
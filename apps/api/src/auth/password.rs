@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version,
+};
+
+use crate::config::PasswordHashConfig;
+
+const BENCHMARK_PASSWORD: &str = "via-discendi-benchmark";
+
+fn password_hasher(config: &PasswordHashConfig) -> Result<Argon2<'static>, String> {
+    let params = Params::new(
+        config.memory_kib,
+        config.iterations,
+        config.parallelism,
+        None,
+    )
+    .map_err(|err| err.to_string())?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+pub fn hash_password(password: &str, config: &PasswordHashConfig) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let argon2 = password_hasher(config)?;
+
+    match argon2.hash_password(password.as_bytes(), &salt) {
+        Ok(hash) => Ok(hash.to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Whether the hash was produced with another algorithm, version or cost than the configured one.
+pub fn needs_rehash(hash: &PasswordHash, config: &PasswordHashConfig) -> bool {
+    if Algorithm::try_from(hash.algorithm) != Ok(Algorithm::Argon2id)
+        || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() != config.memory_kib
+                || params.t_cost() != config.iterations
+                || params.p_cost() != config.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+/// Verifies the password against the stored hash, using the algorithm, version and parameters
+/// encoded in the hash so passwords hashed with older ones still work. On success returns whether
+/// the hash should be replaced with one using the configured parameters. Only the Argon2 family
+/// is supported, hashes of any other algorithm are rejected.
+pub fn verify_password(
+    password: &str,
+    hash: &str,
+    config: &PasswordHashConfig,
+) -> Result<bool, String> {
+    let hash = PasswordHash::new(hash).map_err(|err| err.to_string())?;
+
+    // argon2i and argon2d are verified by the same hasher, it reads the variant from the hash
+    if Algorithm::try_from(hash.algorithm).is_err() {
+        return Err(format!(
+            "Unsupported password hash algorithm {}",
+            hash.algorithm
+        ));
+    }
+
+    match Argon2::default().verify_password(password.as_bytes(), &hash) {
+        Ok(_) => Ok(needs_rehash(&hash, config)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Hashes a dummy password with the configured parameters and returns how long it took, used on
+/// startup to catch parameters that would make login too slow for the machine.
+pub fn benchmark_password_hash(config: &PasswordHashConfig) -> Result<Duration, String> {
+    let start = Instant::now();
+    hash_password(BENCHMARK_PASSWORD, config)?;
+
+    Ok(start.elapsed())
+}
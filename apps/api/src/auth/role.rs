@@ -0,0 +1,74 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Permission {
+    ViewUsers,
+    ManageUsers,
+    RevokeSessions,
+    ModerateRoadmaps,
+    ViewAuditLogs,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        [Role::User, Role::Moderator, Role::Admin]
+            .into_iter()
+            .find(|role| role.as_str() == value)
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::User => &[],
+            Role::Moderator => &[Permission::ViewUsers, Permission::ModerateRoadmaps],
+            Role::Admin => &[
+                Permission::ViewUsers,
+                Permission::ManageUsers,
+                Permission::RevokeSessions,
+                Permission::ModerateRoadmaps,
+                Permission::ViewAuditLogs,
+            ],
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+pub struct UserAccess {
+    pub role: Role,
+    pub disabled_at: Option<NaiveDateTime>,
+}
+
+pub async fn find_user_access(username: &String, pool: &PgPool) -> Result<UserAccess, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT ROLE, DISABLED_AT FROM USERS WHERE USERNAME = $1",
+        username
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(UserAccess {
+        // the column is constrained to the known roles, fallback to the least privileged one
+        role: Role::parse(&record.role).unwrap_or(Role::User),
+        disabled_at: record.disabled_at,
+    })
+}
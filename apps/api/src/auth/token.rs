@@ -0,0 +1,180 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    postgres::{PgPool, PgQueryResult},
+    types::chrono::Local,
+};
+
+use crate::utils::generate_random_str;
+
+const TOKEN_PREFIX: &str = "vdpat_";
+const TOKEN_LENGTH: u8 = 48;
+const TOKEN_ID_LENGTH: u8 = 12;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    ReadRoadmaps,
+    WriteRoadmaps,
+    WriteProgress,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 3] = [
+        TokenScope::ReadRoadmaps,
+        TokenScope::WriteRoadmaps,
+        TokenScope::WriteProgress,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::ReadRoadmaps => "read_roadmaps",
+            TokenScope::WriteRoadmaps => "write_roadmaps",
+            TokenScope::WriteProgress => "write_progress",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<TokenScope> {
+        TokenScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PersonalAccessToken {
+    pub id: String,
+    pub username: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expiry_date: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl PersonalAccessToken {
+    pub fn token_scopes(&self) -> Vec<TokenScope> {
+        self.scopes
+            .iter()
+            .filter_map(|scope| TokenScope::parse(scope))
+            .collect()
+    }
+}
+
+// tokens are long random strings so a fast hash is enough, unlike passwords
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a new token and returns it alongside its plain text value, which is not stored and
+/// can't be retrieved again.
+pub async fn create_personal_access_token(
+    username: &String,
+    name: &String,
+    scopes: &[TokenScope],
+    expiry_date: Option<NaiveDateTime>,
+    pool: &PgPool,
+) -> Result<(PersonalAccessToken, String), sqlx::Error> {
+    let token = format!("{}{}", TOKEN_PREFIX, generate_random_str(TOKEN_LENGTH));
+    let scopes: Vec<String> = scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+
+    let personal_access_token = sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+            INSERT INTO PERSONAL_ACCESS_TOKENS
+                (ID, USERNAME, NAME, TOKEN_HASH, SCOPES, EXPIRY_DATE)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+            RETURNING ID, USERNAME, NAME, SCOPES, EXPIRY_DATE, LAST_USED_AT, CREATED_AT
+        "#,
+        generate_random_str(TOKEN_ID_LENGTH),
+        username,
+        name,
+        hash_token(&token),
+        &scopes,
+        expiry_date
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((personal_access_token, token))
+}
+
+pub async fn get_user_personal_access_tokens(
+    username: &String,
+    pool: &PgPool,
+) -> Result<Vec<PersonalAccessToken>, sqlx::Error> {
+    sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+            SELECT ID, USERNAME, NAME, SCOPES, EXPIRY_DATE, LAST_USED_AT, CREATED_AT
+            FROM PERSONAL_ACCESS_TOKENS
+            WHERE USERNAME = $1
+            ORDER BY CREATED_AT
+        "#,
+        username
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn revoke_personal_access_token(
+    token_id: &String,
+    username: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM PERSONAL_ACCESS_TOKENS WHERE ID = $1 AND USERNAME = $2",
+        token_id,
+        username
+    )
+    .execute(pool)
+    .await
+}
+
+/// Finds the token if it is neither revoked nor expired, without marking it as used.
+pub async fn find_active_personal_access_token(
+    token_id: &String,
+    pool: &PgPool,
+) -> Result<Option<PersonalAccessToken>, sqlx::Error> {
+    sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+            SELECT ID, USERNAME, NAME, SCOPES, EXPIRY_DATE, LAST_USED_AT, CREATED_AT
+            FROM PERSONAL_ACCESS_TOKENS
+            WHERE
+                ID = $1 AND
+                (EXPIRY_DATE IS NULL OR EXPIRY_DATE > $2)
+        "#,
+        token_id,
+        Local::now().naive_utc()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Looks up a non expired token by its plain text value and marks it as used.
+pub async fn authenticate_personal_access_token(
+    token: &str,
+    pool: &PgPool,
+) -> Result<Option<PersonalAccessToken>, sqlx::Error> {
+    sqlx::query_as!(
+        PersonalAccessToken,
+        r#"
+            UPDATE PERSONAL_ACCESS_TOKENS
+            SET LAST_USED_AT = $2
+            WHERE
+                TOKEN_HASH = $1 AND
+                (EXPIRY_DATE IS NULL OR EXPIRY_DATE > $2)
+            RETURNING ID, USERNAME, NAME, SCOPES, EXPIRY_DATE, LAST_USED_AT, CREATED_AT
+        "#,
+        hash_token(token),
+        Local::now().naive_utc()
+    )
+    .fetch_optional(pool)
+    .await
+}
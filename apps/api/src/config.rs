@@ -0,0 +1,200 @@
+use std::env;
+
+const DEFAULT_OIDC_PROVIDER: &str = "oidc";
+const DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+
+// same as the argon2 crate defaults, which follow the OWASP recommendation for argon2id
+const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+
+// the next.js client dev server
+const DEFAULT_ALLOWED_ORIGINS: &str = "http://localhost:3000";
+const DEFAULT_CORS_MAX_AGE_SECS: u32 = 60 * 60;
+
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const DEFAULT_TRASH_PURGE_INTERVAL_SECS: u32 = 60 * 60;
+
+const DEFAULT_WEBSOCKET_FLUSH_INTERVAL_MS: u32 = 1000;
+const DEFAULT_WEBSOCKET_PING_INTERVAL_SECS: u32 = 30;
+const DEFAULT_WEBSOCKET_IDLE_TIMEOUT_SECS: u32 = 90;
+const DEFAULT_WEBSOCKET_MAX_LIFETIME_SECS: u32 = 12 * 60 * 60;
+const DEFAULT_WEBSOCKET_MAX_MESSAGE_BYTES: u32 = 64 * 1024;
+// dragging an area sends a move and a cursor on every frame
+const DEFAULT_WEBSOCKET_MAX_MESSAGES_PER_SEC: u32 = 200;
+
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    // NOTE: provider is the name stored on USER_IDENTITIES, changing it will unlink existing users
+    pub provider: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordHashConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashConfig {
+    fn default() -> Self {
+        PasswordHashConfig {
+            memory_kib: DEFAULT_ARGON2_MEMORY_KIB,
+            iterations: DEFAULT_ARGON2_ITERATIONS,
+            parallelism: DEFAULT_ARGON2_PARALLELISM,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    // origins as sent by browsers, scheme and host with an optional port and no trailing slash
+    pub allowed_origins: Vec<String>,
+    pub max_age_secs: u32,
+}
+
+impl CorsConfig {
+    pub fn is_allowed_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed_origin| allowed_origin == origin)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrashConfig {
+    // deleted roadmaps and areas can be restored until they are purged after this many days
+    pub retention_days: u32,
+    pub purge_interval_secs: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct WebsocketConfig {
+    // moves received on the area websocket are written to the database this often, only the
+    // last position of each area is written
+    pub flush_interval_ms: u32,
+    // the server pings every connection this often, and checks it can still edit the roadmap
+    pub ping_interval_secs: u32,
+    // connections the client sent nothing on for this long, pongs included, are closed
+    pub idle_timeout_secs: u32,
+    // connections are closed after this long whatever happens so clients reconnect with fresh
+    // credentials
+    pub max_lifetime_secs: u32,
+    pub max_message_bytes: u32,
+    // sustained rate, bursts of up to a second worth of messages are let through
+    pub max_messages_per_sec: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub oidc: Option<OidcConfig>,
+    pub password_hash: PasswordHashConfig,
+    pub cors: CorsConfig,
+    pub trash: TrashConfig,
+    pub websocket: WebsocketConfig,
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or(default.to_string())
+}
+
+fn env_number_or(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a positive number, got {}", key, value)),
+        Err(_) => default,
+    }
+}
+
+// for the settings zero makes no sense for, intervals of zero panic
+fn env_positive_number_or(key: &str, default: u32) -> u32 {
+    match env_number_or(key, default) {
+        0 => panic!("{} must be a positive number, got 0", key),
+        value => value,
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        // oidc login is only enabled when the issuer and client are configured
+        let oidc = match (
+            env::var("OIDC_ISSUER_URL"),
+            env::var("OIDC_CLIENT_ID"),
+            env::var("OIDC_REDIRECT_URL"),
+        ) {
+            (Ok(issuer_url), Ok(client_id), Ok(redirect_url)) => Some(OidcConfig {
+                provider: env_or("OIDC_PROVIDER", DEFAULT_OIDC_PROVIDER),
+                issuer_url,
+                client_id,
+                client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+                redirect_url,
+                scopes: env_or("OIDC_SCOPES", DEFAULT_OIDC_SCOPES),
+            }),
+            _ => None,
+        };
+
+        let password_hash = PasswordHashConfig {
+            memory_kib: env_number_or("ARGON2_MEMORY_KIB", DEFAULT_ARGON2_MEMORY_KIB),
+            iterations: env_number_or("ARGON2_ITERATIONS", DEFAULT_ARGON2_ITERATIONS),
+            parallelism: env_number_or("ARGON2_PARALLELISM", DEFAULT_ARGON2_PARALLELISM),
+        };
+
+        let cors = CorsConfig {
+            allowed_origins: env_or("CORS_ALLOWED_ORIGINS", DEFAULT_ALLOWED_ORIGINS)
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            max_age_secs: env_number_or("CORS_MAX_AGE_SECS", DEFAULT_CORS_MAX_AGE_SECS),
+        };
+
+        let trash = TrashConfig {
+            retention_days: env_number_or("TRASH_RETENTION_DAYS", DEFAULT_TRASH_RETENTION_DAYS),
+            purge_interval_secs: env_number_or(
+                "TRASH_PURGE_INTERVAL_SECS",
+                DEFAULT_TRASH_PURGE_INTERVAL_SECS,
+            ),
+        };
+
+        let websocket = WebsocketConfig {
+            flush_interval_ms: env_positive_number_or(
+                "WEBSOCKET_FLUSH_INTERVAL_MS",
+                DEFAULT_WEBSOCKET_FLUSH_INTERVAL_MS,
+            ),
+            ping_interval_secs: env_positive_number_or(
+                "WEBSOCKET_PING_INTERVAL_SECS",
+                DEFAULT_WEBSOCKET_PING_INTERVAL_SECS,
+            ),
+            idle_timeout_secs: env_positive_number_or(
+                "WEBSOCKET_IDLE_TIMEOUT_SECS",
+                DEFAULT_WEBSOCKET_IDLE_TIMEOUT_SECS,
+            ),
+            max_lifetime_secs: env_positive_number_or(
+                "WEBSOCKET_MAX_LIFETIME_SECS",
+                DEFAULT_WEBSOCKET_MAX_LIFETIME_SECS,
+            ),
+            max_message_bytes: env_positive_number_or(
+                "WEBSOCKET_MAX_MESSAGE_BYTES",
+                DEFAULT_WEBSOCKET_MAX_MESSAGE_BYTES,
+            ),
+            max_messages_per_sec: env_positive_number_or(
+                "WEBSOCKET_MAX_MESSAGES_PER_SEC",
+                DEFAULT_WEBSOCKET_MAX_MESSAGES_PER_SEC,
+            ),
+        };
+
+        Config {
+            oidc,
+            password_hash,
+            cors,
+            trash,
+            websocket,
+        }
+    }
+}
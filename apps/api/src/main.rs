@@ -1,20 +1,31 @@
 use axum::{
-    middleware::from_fn,
-    routing::{get, post, put},
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, post, put},
     Router,
 };
-use log::{info, Level, LevelFilter, SetLoggerError};
+use config::Config;
+use log::{info, warn, Level, LevelFilter, SetLoggerError};
 use logger::SimpleLogger;
+use roadmap::hub::RoadmapHub;
 use router_common::RouterGlobalState;
 use sqlx::postgres::PgPoolOptions;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
+mod admin;
+mod audit;
 mod auth;
+mod config;
 mod logger;
 mod roadmap;
 mod router_common;
 mod router_middleware;
 mod utils;
 
+// hashing slower than this makes login noticeably slow and easy to overload
+const SLOW_PASSWORD_HASH: Duration = Duration::from_secs(1);
+// how long open websockets get to write their pending moves when shutting down
+const WEBSOCKET_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
 static LOGGER: SimpleLogger = SimpleLogger {
     allowed_level: Level::Debug,
 };
@@ -33,11 +44,93 @@ async fn main() {
         .unwrap();
 
     init().unwrap();
-    let router_global_state = RouterGlobalState { pool };
+    let config = Config::from_env();
+    if config.oidc.is_none() {
+        info!("OIDC is not configured, single sign on is disabled");
+    }
+
+    let password_hash = &config.password_hash;
+    match auth::password::benchmark_password_hash(password_hash) {
+        Ok(elapsed) => {
+            info!(
+                "Argon2id with memory {} KiB, {} iterations and parallelism {} takes {} ms per hash",
+                password_hash.memory_kib,
+                password_hash.iterations,
+                password_hash.parallelism,
+                elapsed.as_millis()
+            );
+            if elapsed > SLOW_PASSWORD_HASH {
+                warn!("Password hashing is slow, consider lowering ARGON2_MEMORY_KIB or ARGON2_ITERATIONS");
+            }
+        }
+        Err(err) => panic!("Invalid Argon2 parameters: {}", err),
+    }
+
+    info!(
+        "Allowing cross origin requests from {}",
+        config.cors.allowed_origins.join(", ")
+    );
+
+    info!(
+        "Purging trash older than {} days every {} seconds",
+        config.trash.retention_days, config.trash.purge_interval_secs
+    );
+    tokio::spawn(roadmap::purge_trash_periodically(
+        pool.clone(),
+        config.trash.clone(),
+    ));
+
+    info!(
+        "Writing area moves every {} ms",
+        config.websocket.flush_interval_ms
+    );
+    // a dead connection can go unnoticed for up to a ping and an idle timeout, its editor gets
+    // as long to come back and resume
+    let roadmap_hub = RoadmapHub::new(Duration::from_secs(
+        u64::from(config.websocket.idle_timeout_secs)
+            + u64::from(config.websocket.ping_interval_secs),
+    ));
+
+    let router_global_state = RouterGlobalState {
+        pool,
+        config: Arc::new(config),
+        roadmap_hub: roadmap_hub.clone(),
+    };
 
     info!("Configuring routers...");
 
-    let main_router = Router::new()
+    let main_router = main_router(router_global_state);
+
+    info!("Starting server on port {}", port);
+
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
+        .await
+        .unwrap();
+    // the peer address is recorded on the audit log
+    axum::serve(
+        listener,
+        main_router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
+
+    info!("Closing roadmap editors...");
+    roadmap_hub.close();
+    if tokio::time::timeout(
+        WEBSOCKET_SHUTDOWN_TIMEOUT,
+        roadmap_hub.wait_for_subscriptions(),
+    )
+    .await
+    .is_err()
+    {
+        warn!("Roadmap editors did not close in time, their last moves may be lost");
+    }
+}
+
+// every route of the api along with its middlewares
+fn main_router(router_global_state: RouterGlobalState) -> Router {
+    Router::new()
         .route("/", get(|| async { "Hello, World!" }))
         .route("/api/v1/login", post(auth::api::login_router))
         .route("/api/v1/sign_up", post(auth::api::sign_up_router))
@@ -45,6 +138,17 @@ async fn main() {
             "/api/v1/refresh_token",
             get(auth::api::refresh_token_router),
         )
+        .route("/api/v1/tokens", post(auth::api::create_token_router))
+        .route("/api/v1/tokens", get(auth::api::get_tokens_router))
+        .route(
+            "/api/v1/tokens/:token_id",
+            delete(auth::api::revoke_token_router),
+        )
+        .route("/api/v1/oidc/login", get(auth::api::oidc_login_router))
+        .route(
+            "/api/v1/oidc/callback",
+            get(auth::api::oidc_callback_router),
+        )
         .route("/api/v1/roadmaps", post(roadmap::api::add_roadmap_router))
         .route("/api/v1/roadmaps", put(roadmap::api::update_roadmap_router))
         .route("/api/v1/roadmaps", get(roadmap::api::find_roadmap_router))
@@ -52,17 +156,138 @@ async fn main() {
             "/api/v1/roadmaps/:roadmap_id",
             get(roadmap::api::get_roadmap_detail_router),
         )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id",
+            delete(roadmap::api::delete_roadmap_router),
+        )
         .route(
             "/api/v1/roadmaps/:roadmap_id/areas",
             get(roadmap::api::area_websocket_router),
         )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/areas:batch",
+            post(roadmap::api::area_batch_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/areas/:area_id",
+            delete(roadmap::api::delete_area_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/history",
+            get(roadmap::api::get_roadmap_history_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/publish",
+            put(roadmap::api::publish_roadmap_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/unpublish",
+            put(roadmap::api::unpublish_roadmap_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/visibility",
+            put(roadmap::api::set_visibility_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/share_token",
+            post(roadmap::api::rotate_share_token_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/fork",
+            post(roadmap::api::fork_roadmap_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/collaborators",
+            get(roadmap::api::get_collaborators_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/collaborators",
+            post(roadmap::api::invite_collaborator_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/collaborators/accept",
+            post(roadmap::api::accept_collaboration_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/collaborators/:username",
+            delete(roadmap::api::remove_collaborator_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/editors",
+            get(roadmap::api::get_editors_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/owner",
+            put(roadmap::api::transfer_ownership_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/restore",
+            post(roadmap::api::restore_roadmap_router),
+        )
+        .route(
+            "/api/v1/roadmaps/:roadmap_id/areas/:area_id/restore",
+            post(roadmap::api::restore_area_router),
+        )
+        .route("/api/v1/trash", get(roadmap::api::get_trash_router))
+        .route("/api/v1/tags", get(roadmap::api::autocomplete_tags_router))
+        .route(
+            "/api/v1/me/roadmaps",
+            get(roadmap::api::get_my_roadmaps_router),
+        )
+        .route(
+            "/api/v1/me/invitations",
+            get(roadmap::api::get_my_invitations_router),
+        )
+        .route(
+            "/api/v1/users/:username/roadmaps",
+            get(roadmap::api::get_user_roadmaps_router),
+        )
+        .route("/api/v1/admin/users", get(admin::api::get_users_router))
+        .route(
+            "/api/v1/admin/users/:username",
+            put(admin::api::update_user_router),
+        )
+        .route(
+            "/api/v1/admin/users/:username/sessions",
+            delete(admin::api::revoke_user_sessions_router),
+        )
+        .route(
+            "/api/v1/admin/roadmaps/:roadmap_id/unpublish",
+            put(admin::api::unpublish_roadmap_router),
+        )
+        .route(
+            "/api/v1/admin/audit_logs",
+            get(audit::api::get_audit_logs_router),
+        )
+        .layer(from_fn_with_state(
+            router_global_state.clone(),
+            router_middleware::csrf_protection,
+        ))
+        // outside of the csrf check so preflight requests are answered directly
+        .layer(router_middleware::cors_layer(
+            &router_global_state.config.cors,
+        ))
         .layer(from_fn(router_middleware::trace_time))
-        .with_state(router_global_state);
+        .with_state(router_global_state)
+}
 
-    info!("Starting server on port {}", port);
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
-        .await
-        .unwrap();
-    axum::serve(listener, main_router).await.unwrap();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutting down...");
 }
@@ -1,57 +1,116 @@
 use axum::{
     body::Body,
-    extract::{ws::WebSocket, Path, Query, State, WebSocketUpgrade},
-    http::{Response, StatusCode},
+    extract::{rejection::JsonRejection, ws::close_code, Path, Query, State, WebSocketUpgrade},
+    http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
     Json,
 };
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use log::{error, info};
 
 use crate::{
-    router_common::{CreateResponse, RouterGlobalState},
+    audit::{record_audit_event, AuditEvent, RequestContext},
+    auth::{extractor::AuthenticatedUser, token::TokenScope},
+    router_common::{ApiError, CreateResponse, RouterGlobalState},
+    router_middleware::check_origin,
     utils::generate_random_str,
 };
 
 use super::{
-    add_areas, add_roadmap, find_roadmap, get_roadmap_by_id, roadmap_exist, update_areas,
-    update_roadmap, Areas, Roadmaps,
+    accept_collaboration, add_roadmap_with_details,
+    batch::{
+        apply_area_batch, AppliedBatchItem, AreaBatch, AreaBatchError, BatchOperation,
+        MAX_AREA_BATCH_ITEMS,
+    },
+    codec::{Codec, SUBPROTOCOLS},
+    delete_area_with_history, delete_roadmap, find_roadmap, fork_roadmap, get_collaborators,
+    get_pending_invitations, get_roadmap_by_id, get_roadmap_role, get_trashed_areas,
+    get_trashed_roadmaps, get_user_roadmaps,
+    history::{get_area_changes, AreaChange, AreaChangeFilter, ChangeKind, ChangeSource},
+    hub::{ResumePoint, RoadmapChange},
+    invite_collaborator, normalize_content_language,
+    protocol::{
+        negotiate_protocol_version, AreaSnapshot, ErrorCode, ServerEvent, CLOSE_FORBIDDEN,
+        CLOSE_NOT_FOUND, CLOSE_UNAUTHORIZED, SUPPORTED_PROTOCOL_VERSIONS,
+    },
+    publish_roadmap, remove_collaborator, restore_area_with_history, restore_roadmap,
+    rotate_share_token, set_roadmap_visibility,
+    tag::{autocomplete_tags, find_roadmap_tag_facets, normalize_tag, normalize_tags},
+    transfer_roadmap_ownership, unpublish_roadmap, update_roadmap_with_details,
+    validate_roadmap_for_publish, websocket, AreaField, Collaborator, Difficulty, RoadmapMetadata,
+    RoadmapRole, RoadmapSearch, RoadmapSort, RoadmapVisibility, RoadmapWithAreas, Roadmaps,
+    SortOrder, UserRoadmapsQuery, DEFAULT_ID_LENGTH,
 };
 
 #[derive(Deserialize)]
 pub struct AddRoadmapRequest {
-    user_id: String,
     title: String,
     description: Option<String>,
+    tags: Option<Vec<String>>,
+    metadata: Option<RoadmapMetadata>,
 }
 
 #[derive(Deserialize)]
 pub struct UpdateRoadmapRequest {
     pub roadmap_id: String,
-    pub user_id: String,
     pub title: String,
     pub description: Option<String>,
+    // the tags are left as they are when missing
+    pub tags: Option<Vec<String>>,
+    // replaced as a whole when present
+    pub metadata: Option<RoadmapMetadata>,
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct UpsertAreaRequest {
-    pub area_id: Option<String>,
-    pub parent_id: Option<String>,
-    pub title: String,
-    pub description: Option<String>,
-
-    pub x: f64,
-    pub y: f64,
+#[derive(Deserialize)]
+pub struct AreaWebsocketParams {
+    // the latest version is used when missing
+    protocol_version: Option<u32>,
+    // both are needed to resume a session, from the welcome and the last frame received
+    stream_id: Option<String>,
+    resume_from: Option<u64>,
 }
 
 #[derive(Deserialize)]
 pub struct FindRoadmapParams {
     title: Option<String>,
+    // comma separated, roadmaps need every one of them
+    tags: Option<String>,
+    difficulty: Option<Difficulty>,
+    content_language: Option<String>,
+    min_hours: Option<f64>,
+    max_hours: Option<f64>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RoadmapAccessParams {
+    // required for unlisted roadmaps
+    share_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetVisibilityRequest {
+    visibility: RoadmapVisibility,
+}
+
+#[derive(Deserialize)]
+pub struct UserRoadmapsParams {
+    sort: Option<RoadmapSort>,
+    order: Option<SortOrder>,
     limit: Option<i64>,
     offset: Option<i64>,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct UserRoadmapsResult {
+    roadmaps: Vec<RoadmapResponse>,
+    limit: i64,
+    offset: i64,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct RoadmapResponse {
     id: String,
@@ -65,6 +124,15 @@ pub struct AreaResponse {
     parent_id: Option<String>,
     title: String,
     description: Option<String>,
+    estimated_hours: Option<f64>,
+    // what editors base their changes on
+    revision: i64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TagCountResponse {
+    name: String,
+    roadmap_count: i64,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -72,6 +140,19 @@ pub struct FindRoadmapResult {
     roadmaps: Vec<RoadmapResponse>,
     page: i64,
     total: i64,
+    // roadmaps per tag across the whole search
+    facets: Vec<TagCountResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct TagAutocompleteParams {
+    prefix: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TagAutocompleteResult {
+    tags: Vec<TagCountResponse>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -79,30 +160,335 @@ pub struct RoadmapWithAreasResponse {
     roadmap_id: String,
     roadmap_title: String,
     roadmap_description: Option<String>,
+    publisher: String,
+    published: bool,
+    published_at: Option<NaiveDateTime>,
+    // id of the roadmap this one was forked from
+    forked_from: Option<String>,
+    // published public forks only
+    fork_count: i64,
+    visibility: RoadmapVisibility,
+    // only returned to the owner
+    share_token: Option<String>,
+    tags: Vec<String>,
+    metadata: RoadmapMetadata,
+    // sum of the estimates of the areas
+    total_estimated_hours: f64,
     areas: Vec<AreaResponse>,
 }
 
-const DEFAULT_ID_LENGTH: u8 = 12;
+#[derive(Deserialize, Serialize)]
+pub struct PublishRoadmapResponse {
+    is_successful: bool,
+    message: String,
+    id: Option<String>,
+    // reasons the roadmap can't be published
+    errors: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct VisibilityResponse {
+    is_successful: bool,
+    message: String,
+    id: Option<String>,
+    visibility: Option<RoadmapVisibility>,
+    share_token: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TrashedRoadmapResponse {
+    id: String,
+    title: String,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TrashedAreaResponse {
+    id: String,
+    roadmap_id: String,
+    parent_id: Option<String>,
+    title: String,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TrashResult {
+    roadmaps: Vec<TrashedRoadmapResponse>,
+    areas: Vec<TrashedAreaResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct InviteCollaboratorRequest {
+    username: String,
+    role: RoadmapRole,
+}
+
+#[derive(Deserialize)]
+pub struct TransferOwnershipRequest {
+    username: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CollaboratorResponse {
+    roadmap_id: String,
+    username: String,
+    role: String,
+    invited_by: Option<String>,
+    // pending invitations have no accepted_at
+    accepted_at: Option<NaiveDateTime>,
+    created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CollaboratorsResult {
+    collaborators: Vec<CollaboratorResponse>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct EditorResponse {
+    username: String,
+    // the same user can have the roadmap open more than once
+    connections: usize,
+    joined_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct EditorsResult {
+    editors: Vec<EditorResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct RoadmapHistoryParams {
+    area_id: Option<String>,
+    author: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AreaChangeResponse {
+    id: i64,
+    area_id: String,
+    author: String,
+    kind: ChangeKind,
+    source: ChangeSource,
+    // the change undone or redone
+    target_id: Option<i64>,
+    before: Option<AreaSnapshot>,
+    after: Option<AreaSnapshot>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RoadmapHistoryResult {
+    changes: Vec<AreaChangeResponse>,
+    limit: i64,
+    offset: i64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Applied,
+    Rejected,
+    Conflict,
+    // another item was rejected so nothing was applied
+    Skipped,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AreaBatchItemResponse {
+    operation: BatchOperation,
+    // position of the item in its list of the request
+    index: usize,
+    status: BatchItemStatus,
+    // set on creates
+    temp_id: Option<String>,
+    // set once applied, the server id of created areas
+    area_id: Option<String>,
+    revision: Option<i64>,
+    // set on rejected items
+    code: Option<ErrorCode>,
+    message: Option<String>,
+    // set on conflicts, the fields changed since the base revision and the area as it is now
+    fields: Option<Vec<AreaField>>,
+    area: Option<AreaSnapshot>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AreaBatchResponse {
+    is_successful: bool,
+    message: String,
+    results: Vec<AreaBatchItemResponse>,
+}
+
+impl AreaBatchItemResponse {
+    fn new(operation: BatchOperation, index: usize, status: BatchItemStatus) -> Self {
+        AreaBatchItemResponse {
+            operation,
+            index,
+            status,
+            temp_id: None,
+            area_id: None,
+            revision: None,
+            code: None,
+            message: None,
+            fields: None,
+            area: None,
+        }
+    }
+}
+
+impl From<AreaChange> for AreaChangeResponse {
+    fn from(change: AreaChange) -> Self {
+        AreaChangeResponse {
+            id: change.id,
+            area_id: change.area_id,
+            author: change.author,
+            kind: change.kind,
+            source: change.source,
+            target_id: change.target_id,
+            before: change.before,
+            after: change.after,
+            created_at: change.created_at,
+        }
+    }
+}
+
 const DEFAULT_LIMIT: i64 = 10;
 const DEFAULT_OFFSET: i64 = 0;
+const MAX_LIMIT: i64 = 100;
+
+// write scope plus at least `minimum` access on the roadmap, returns the role of the user
+async fn require_roadmap_role(
+    roadmap_id: &str,
+    user: &AuthenticatedUser,
+    minimum: RoadmapRole,
+    router_global_state: &RouterGlobalState,
+) -> Result<RoadmapRole, ApiError> {
+    let scope = match minimum {
+        RoadmapRole::Viewer => TokenScope::ReadRoadmaps,
+        RoadmapRole::Editor | RoadmapRole::Owner => TokenScope::WriteRoadmaps,
+    };
+    user.require_scope(scope, &router_global_state.pool).await?;
+
+    match get_roadmap_role(
+        roadmap_id.to_string(),
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(Some(role)) if role >= minimum => Ok(role),
+        Ok(_) => {
+            user.record_denial(
+                Some(roadmap_id),
+                &format!("{} role", minimum.as_str()),
+                &router_global_state.pool,
+            )
+            .await;
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                match minimum {
+                    RoadmapRole::Owner => "Only the owner can do this on the roadmap",
+                    RoadmapRole::Editor => "Only the owner and editors can edit the roadmap",
+                    RoadmapRole::Viewer => "You are not a collaborator of the roadmap",
+                },
+            ))
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            Err(ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found"))
+        }
+        Err(err) => {
+            error!("[roadmap][require_roadmap_role] {}", err);
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on checking the roadmap, please try again",
+            ))
+        }
+    }
+}
+
+// published roadmaps are visible according to their visibility, the owner and collaborators
+// can always see them
+async fn can_view_roadmap(
+    roadmap: &RoadmapWithAreas,
+    username: Option<&String>,
+    share_token: Option<&String>,
+    router_global_state: &RouterGlobalState,
+) -> bool {
+    if roadmap.published {
+        match roadmap.visibility {
+            RoadmapVisibility::Public => return true,
+            RoadmapVisibility::Unlisted
+                if share_token.is_some() && share_token == roadmap.share_token.as_ref() =>
+            {
+                return true
+            }
+            _ => {}
+        }
+    }
+
+    let Some(username) = username else {
+        return false;
+    };
+
+    match get_roadmap_role(
+        roadmap.roadmap_id.clone(),
+        username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(role) => role.is_some(),
+        Err(err) => {
+            error!("[roadmap][can_view_roadmap] {}", err);
+            false
+        }
+    }
+}
 
 pub async fn add_roadmap_router(
     State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
     Json(request): Json<AddRoadmapRequest>,
 ) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::WriteRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    let tags = match normalize_tags(&request.tags.unwrap_or_default()) {
+        Ok(tags) => tags,
+        Err(message) => return ApiError::new(StatusCode::BAD_REQUEST, &message).into_response(),
+    };
+    let metadata = match request.metadata.map(RoadmapMetadata::normalize).transpose() {
+        Ok(metadata) => metadata,
+        Err(message) => return ApiError::new(StatusCode::BAD_REQUEST, &message).into_response(),
+    };
+
     let roadmap_id = generate_random_str(DEFAULT_ID_LENGTH);
     let roadmap = Roadmaps {
         id: roadmap_id.to_string(),
         title: request.title,
         description: request.description,
-        publisher: request.user_id,
+        publisher: user.username,
         published: None,
         created_at: None,
         updated_at: None,
     };
 
-    let roadmap_added = match add_roadmap(roadmap, &router_global_state.pool).await {
-        Ok(result) => result.rows_affected() == 1,
+    let roadmap_added = match add_roadmap_with_details(
+        roadmap,
+        &tags,
+        metadata.as_ref(),
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(roadmap_added) => roadmap_added,
         Err(err) => {
             error!(
                 "[roadmap][add_roadmap] error on adding roadmap with cause {}",
@@ -141,23 +527,52 @@ pub async fn add_roadmap_router(
 
 pub async fn update_roadmap_router(
     State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
     Json(request): Json<UpdateRoadmapRequest>,
 ) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &request.roadmap_id,
+        &user,
+        RoadmapRole::Editor,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    let tags = match request.tags.as_deref().map(normalize_tags).transpose() {
+        Ok(tags) => tags,
+        Err(message) => return ApiError::new(StatusCode::BAD_REQUEST, &message).into_response(),
+    };
+    let metadata = match request.metadata.map(RoadmapMetadata::normalize).transpose() {
+        Ok(metadata) => metadata,
+        Err(message) => return ApiError::new(StatusCode::BAD_REQUEST, &message).into_response(),
+    };
+
+    // the owner and editors are matched on update, other users will not affect any row
     let roadmap = Roadmaps {
         id: request.roadmap_id.to_string(),
         title: request.title,
         description: request.description,
-        publisher: request.user_id,
+        publisher: user.username,
         published: None,
         created_at: None,
         updated_at: Some(Utc::now().naive_utc()),
     };
 
-    let update_result = match update_roadmap(roadmap, &router_global_state.pool).await {
-        Ok(result) => result.rows_affected() == 1,
+    let update_result = match update_roadmap_with_details(
+        roadmap,
+        tags.as_deref(),
+        metadata.as_ref(),
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(update_result) => update_result,
         Err(err) => {
             error!(
-                "[roadmap][add_roadmap] error on adding roadmap with cause {}",
+                "[roadmap][update_roadmap] error on updating roadmap with cause {}",
                 err
             );
             false
@@ -198,9 +613,42 @@ pub async fn find_roadmap_router(
     let limit = pagination.limit.unwrap_or(DEFAULT_LIMIT);
     let offset = pagination.offset.unwrap_or(DEFAULT_OFFSET);
     let title = pagination.title.unwrap_or("".to_string());
+    let tags: Vec<String> = pagination
+        .tags
+        .unwrap_or_default()
+        .split(',')
+        .filter(|tag| !tag.trim().is_empty())
+        .map(String::from)
+        .collect();
+    let tags = match normalize_tags(&tags) {
+        Ok(tags) => tags,
+        Err(message) => return ApiError::new(StatusCode::BAD_REQUEST, &message).into_response(),
+    };
+    let content_language = match pagination.content_language.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(language) => match normalize_content_language(language) {
+            Some(language) => Some(language),
+            None => {
+                return ApiError::new(StatusCode::BAD_REQUEST, "Invalid content language")
+                    .into_response()
+            }
+        },
+    };
 
-    match find_roadmap(title, limit, offset, &router_global_state.pool).await {
-        Ok(result) => {
+    let search = RoadmapSearch {
+        title,
+        tags,
+        difficulty: pagination.difficulty,
+        content_language,
+        min_hours: pagination.min_hours,
+        max_hours: pagination.max_hours,
+    };
+    let pool = &router_global_state.pool;
+    match (
+        find_roadmap(&search, limit, offset, pool).await,
+        find_roadmap_tag_facets(&search, pool).await,
+    ) {
+        (Ok(result), Ok(facets)) => {
             let mut roadmap_responses: Vec<_> = Vec::new();
 
             for roadmap in result {
@@ -215,6 +663,13 @@ pub async fn find_roadmap_router(
                 roadmaps: roadmap_responses,
                 page: offset,
                 total: limit,
+                facets: facets
+                    .into_iter()
+                    .map(|facet| TagCountResponse {
+                        name: facet.name,
+                        roadmap_count: facet.roadmap_count,
+                    })
+                    .collect(),
             })
             .unwrap();
 
@@ -223,7 +678,7 @@ pub async fn find_roadmap_router(
                 .body(Body::from(response))
                 .unwrap();
         }
-        Err(err) => {
+        (Err(err), _) | (_, Err(err)) => {
             error!(
                 "[roadmap][find_roadmap] error on finding roadmap with cause {}",
                 err
@@ -246,10 +701,33 @@ pub async fn find_roadmap_router(
 
 pub async fn get_roadmap_detail_router(
     State(router_global_state): State<RouterGlobalState>,
+    user: Option<AuthenticatedUser>,
     Path(roadmap_id): Path<String>,
+    Query(params): Query<RoadmapAccessParams>,
 ) -> Response<Body> {
+    let username = user.map(|user| user.username);
+
     match get_roadmap_by_id(roadmap_id.clone(), &router_global_state.pool).await {
         Ok(result) => {
+            // roadmaps the user can't see are reported as missing
+            if !can_view_roadmap(
+                &result,
+                username.as_ref(),
+                params.share_token.as_ref(),
+                &router_global_state,
+            )
+            .await
+            {
+                return ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response();
+            }
+
+            // the share link is managed by the owner
+            let share_token = if username.as_ref() == Some(&result.publisher) {
+                result.share_token
+            } else {
+                None
+            };
+
             let mut area_responses: Vec<AreaResponse> = Vec::new();
 
             for area in result.areas {
@@ -258,6 +736,8 @@ pub async fn get_roadmap_detail_router(
                     parent_id: area.parent_id,
                     title: area.title,
                     description: area.description,
+                    estimated_hours: area.estimated_hours,
+                    revision: area.revision,
                 });
             }
 
@@ -265,6 +745,16 @@ pub async fn get_roadmap_detail_router(
                 roadmap_id: result.roadmap_id,
                 roadmap_title: result.roadmap_title,
                 roadmap_description: result.roadmap_description,
+                publisher: result.publisher,
+                published: result.published,
+                published_at: result.published_at,
+                forked_from: result.forked_from,
+                fork_count: result.fork_count,
+                visibility: result.visibility,
+                share_token,
+                tags: result.tags,
+                metadata: result.metadata,
+                total_estimated_hours: result.total_estimated_hours,
                 areas: area_responses,
             };
 
@@ -275,6 +765,9 @@ pub async fn get_roadmap_detail_router(
                 .body(Body::from(response))
                 .unwrap();
         }
+        Err(sqlx::Error::RowNotFound) => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response()
+        }
         Err(err) => {
             error!(
                 "[roadmap][get_roadmap_by_id] error on finding roadmap {} with cause {}",
@@ -296,202 +789,1425 @@ pub async fn get_roadmap_detail_router(
     }
 }
 
-//TODO: @Performance, this would probably will be slow near future. it might have been better to
-//just update all record at once?
-// this will be really slow if the update is too much, one way to optimize this is to
-// 1. put tick rate like in video game, in this case for every 1 or 3 seconds might be fine.
-// 2. decide whether we should use buffered approach or temporary table or let it be for now.
-// 3. on the client, it's mandatory to optimize for only sending areas that only changes, don't
-//    send data that dont change to reduce load on the network
-// 4. Using compression for compressing message or a better protocol on the websocket instead of
-//    using json, but for now we let it be.
-// 5. don't allow for client to connect on the same roadmap on different socket, it will cause
-//    complicated conflict resolution
-async fn roadmap_area_websocket(
-    mut socket: WebSocket,
-    router_global_state: RouterGlobalState,
-    roadmap_id: String,
-) {
-    match roadmap_exist(roadmap_id.clone(), &router_global_state.pool).await {
-        Ok(value) => {
-            println!("{} exits? {}", roadmap_id.clone(), value);
-            if !value {
-                info!("[roadmap_area_websocket] attempted to modify non existing roadmap");
-                let response = CreateResponse {
-                    is_successful: false,
-                    message: "Fail on connecting the editing the roadmap, please try again"
-                        .to_string(),
-                    id: None,
-                };
-                let _ = socket
-                    .send(axum::extract::ws::Message::Text(
-                        serde_json::to_string(&response).unwrap(),
-                    ))
-                    .await;
-                return;
-            }
+pub async fn area_websocket_router(
+    ws: WebSocketUpgrade,
+    State(router_global_state): State<RouterGlobalState>,
+    headers: HeaderMap,
+    user: Option<AuthenticatedUser>,
+    Path(roadmap_id): Path<String>,
+    Query(params): Query<AreaWebsocketParams>,
+) -> Response<Body> {
+    // websocket upgrades are not subject to cors, so the browser origin is checked here
+    if let Err(err) = check_origin(&headers, &router_global_state.config.cors) {
+        return err.into_response();
+    }
+
+    let Some(protocol_version) = negotiate_protocol_version(params.protocol_version) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unsupported protocol version, supported versions are {:?}",
+                SUPPORTED_PROTOCOL_VERSIONS
+            ),
+        )
+        .into_response();
+    };
+
+    let requested_subprotocols = headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok());
+    let Some(codec) = Codec::negotiate(requested_subprotocols) else {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Unsupported websocket subprotocol, supported subprotocols are {:?}",
+                SUBPROTOCOLS
+            ),
+        )
+        .into_response();
+    };
+    // the subprotocol is only echoed back when the client asked for one
+    let ws = match requested_subprotocols {
+        Some(_) => ws.protocols([codec.subprotocol()]),
+        None => ws,
+    };
+    // oversized messages are refused while being read instead of once buffered, compressed ones
+    // once inflated past the same size
+    let max_message_bytes = router_global_state.config.websocket.max_message_bytes as usize;
+    let ws = ws
+        .max_message_size(max_message_bytes)
+        .max_frame_size(max_message_bytes);
+    let codec = codec.with_max_inflated_size(max_message_bytes);
+
+    let resume = match (params.stream_id, params.resume_from) {
+        (Some(stream_id), Some(sequence)) => Some(ResumePoint {
+            stream_id,
+            sequence,
+        }),
+        _ => None,
+    };
+
+    // refused after the upgrade, with the reason on the close frame, as browsers don't expose the
+    // status of a failed upgrade
+    let Some(user) = user else {
+        return ws.on_upgrade(|socket| {
+            websocket::refuse_connection(
+                socket,
+                CLOSE_UNAUTHORIZED,
+                "Please login before continuing".to_string(),
+            )
+        });
+    };
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Editor,
+        &router_global_state,
+    )
+    .await
+    {
+        let code = match err.status {
+            StatusCode::UNAUTHORIZED => CLOSE_UNAUTHORIZED,
+            StatusCode::FORBIDDEN => CLOSE_FORBIDDEN,
+            StatusCode::NOT_FOUND => CLOSE_NOT_FOUND,
+            _ => close_code::ERROR,
+        };
+        return ws
+            .on_upgrade(move |socket| websocket::refuse_connection(socket, code, err.message));
+    }
+
+    ws.on_upgrade(move |socket| {
+        websocket::roadmap_area_websocket(
+            socket,
+            router_global_state,
+            roadmap_id,
+            user,
+            protocol_version,
+            codec,
+            resume,
+        )
+    })
+}
+
+pub async fn delete_roadmap_router(
+    State(router_global_state): State<RouterGlobalState>,
+    context: RequestContext,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match delete_roadmap(roadmap_id.clone(), &router_global_state.pool).await {
+        Ok(result) if result.rows_affected() == 0 => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response()
+        }
+        Ok(_) => {
+            record_audit_event(
+                AuditEvent::RoadmapDeleted,
+                Some(&user.username),
+                Some(&roadmap_id),
+                None,
+                &context,
+                &router_global_state.pool,
+            )
+            .await;
+
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Roadmap deleted"),
+                id: Some(roadmap_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
         }
         Err(err) => {
-            error!("[roadmap_area_websocket]{}", err);
-            let response = CreateResponse {
-                is_successful: false,
-                message: "Fail on connecting the editing the roadmap, please try again".to_string(),
-                id: None,
-            };
-            let _ = socket
-                .send(axum::extract::ws::Message::Text(
-                    serde_json::to_string(&response).unwrap(),
-                ))
-                .await;
-            return;
-        }
-    }
-
-    while let Some(msg) = socket.recv().await {
-        match msg {
-            Ok(message) => {
-                let message_text = message.to_text();
-                let upsert_area_request: Result<UpsertAreaRequest, _> =
-                    serde_json::from_str(message_text.unwrap());
-
-                match upsert_area_request {
-                    Ok(request) => match request.area_id {
-                        Some(area_id) => {
-                            let area = Areas {
-                                id: area_id,
-                                parent_id: request.parent_id,
-                                roadmap_id: roadmap_id.to_string(),
-                                title: request.title,
-                                description: request.description,
-                                x: request.x,
-                                y: request.y,
-                                created_at: None,
-                                updated_at: Some(Utc::now().naive_utc()),
-                            };
-                            match update_areas(area, &router_global_state.pool).await {
-                                Ok(_) => {
-                                    let response = serde_json::to_string(&CreateResponse {
-                                        is_successful: true,
-                                        message: String::from("Area updated"),
-                                        id: None,
-                                    })
-                                    .unwrap();
-
-                                    if let Err(err) = socket
-                                        .send(axum::extract::ws::Message::Text(response))
-                                        .await
-                                    {
-                                        error!("[roadmap_area_websocket] {}", err);
-                                    }
-                                }
-                                Err(err) => {
-                                    error!("[roadmap_area_websocket] {}", err);
-
-                                    let response = serde_json::to_string(&CreateResponse {
-                                        is_successful: false,
-                                        message: String::from(
-                                            "Fail updating area please try again",
-                                        ),
-                                        id: None,
-                                    })
-                                    .unwrap();
-
-                                    if let Err(err) = socket
-                                        .send(axum::extract::ws::Message::Text(response))
-                                        .await
-                                    {
-                                        error!("[roadmap_area_websocket] {}", err);
-                                    }
-                                }
-                            }
-                        }
-                        None => {
-                            let area_id = generate_random_str(DEFAULT_ID_LENGTH);
-                            let area = Areas {
-                                id: area_id.to_string(),
-                                parent_id: request.parent_id,
-                                roadmap_id: roadmap_id.to_string(),
-                                title: request.title,
-                                description: request.description,
-                                x: request.x,
-                                y: request.y,
-                                created_at: None,
-                                updated_at: Some(Utc::now().naive_utc()),
-                            };
-
-                            match add_areas(area, &router_global_state.pool).await {
-                                Ok(_) => {
-                                    let response = serde_json::to_string(&CreateResponse {
-                                        is_successful: true,
-                                        message: String::from("Area created"),
-                                        id: Some(area_id),
-                                    })
-                                    .unwrap();
-
-                                    if let Err(err) = socket
-                                        .send(axum::extract::ws::Message::Text(response))
-                                        .await
-                                    {
-                                        error!("[roadmap_area_websocket] {}", err);
-                                    }
-                                }
-                                Err(err) => {
-                                    error!("[roadmap_area_websocket] {}", err);
-
-                                    let response = serde_json::to_string(&CreateResponse {
-                                        is_successful: false,
-                                        message: String::from(
-                                            "Fail creating area please try again",
-                                        ),
-                                        id: None,
-                                    })
-                                    .unwrap();
-
-                                    if let Err(err) = socket
-                                        .send(axum::extract::ws::Message::Text(response))
-                                        .await
-                                    {
-                                        error!("[roadmap_area_websocket] {}", err);
-                                    }
-                                }
-                            }
-                        }
+            error!(
+                "[roadmap][delete_roadmap] error on deleting roadmap with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on deleting roadmap please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn delete_area_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path((roadmap_id, area_id)): Path<(String, String)>,
+) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Editor,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    match delete_area_with_history(
+        &roadmap_id,
+        &area_id,
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(false) => ApiError::new(StatusCode::NOT_FOUND, "Area not found").into_response(),
+        Ok(true) => {
+            router_global_state.roadmap_hub.publish(
+                &roadmap_id,
+                RoadmapChange {
+                    connection_id: None,
+                    sequence: None,
+                    event: ServerEvent::AreaDeleted {
+                        editor: user.username,
+                        area_id: area_id.clone(),
                     },
-                    Err(err) => {
-                        error!(
-                            "[roadmap_area_websocket] fail on parsing request with cause {}",
-                            err
-                        );
-
-                        let response = serde_json::to_string(&CreateResponse {
-                            is_successful: false,
-                            message: String::from("Fail creating area please try again"),
-                            id: None,
-                        })
-                        .unwrap();
-
-                        if let Err(err) = socket
-                            .send(axum::extract::ws::Message::Text(response))
-                            .await
-                        {
-                            error!("[roadmap_area_websocket] {}", err);
-                        }
-
-                        continue;
-                    }
-                }
-            }
-            Err(_err) => {
-                continue;
-            }
+                },
+            );
+
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Area deleted"),
+                id: Some(area_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][delete_area] error on deleting area with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on deleting area please try again",
+            )
+            .into_response()
         }
     }
 }
 
-pub async fn area_websocket_router(
-    ws: WebSocketUpgrade,
+pub async fn get_trash_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::ReadRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    let pool = &router_global_state.pool;
+    let trash = match (
+        get_trashed_roadmaps(&user.username, pool).await,
+        get_trashed_areas(&user.username, pool).await,
+    ) {
+        (Ok(roadmaps), Ok(areas)) => TrashResult {
+            roadmaps: roadmaps
+                .into_iter()
+                .map(|roadmap| TrashedRoadmapResponse {
+                    id: roadmap.id,
+                    title: roadmap.title,
+                    deleted_at: roadmap.deleted_at,
+                })
+                .collect(),
+            areas: areas
+                .into_iter()
+                .map(|area| TrashedAreaResponse {
+                    id: area.id,
+                    roadmap_id: area.roadmap_id,
+                    parent_id: area.parent_id,
+                    title: area.title,
+                    deleted_at: area.deleted_at,
+                })
+                .collect(),
+        },
+        (Err(err), _) | (_, Err(err)) => {
+            error!(
+                "[roadmap][get_trash] error on getting trash with cause {}",
+                err
+            );
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting trash please try again",
+            )
+            .into_response();
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(serde_json::to_string(&trash).unwrap()))
+        .unwrap()
+}
+
+pub async fn restore_roadmap_router(
     State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
     Path(roadmap_id): Path<String>,
 ) -> Response<Body> {
-    ws.on_upgrade(|socket| roadmap_area_websocket(socket, router_global_state, roadmap_id))
+    if let Err(err) = user
+        .require_scope(TokenScope::WriteRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    match restore_roadmap(
+        roadmap_id.clone(),
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found in trash").into_response()
+        }
+        Ok(_) => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Roadmap restored"),
+                id: Some(roadmap_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][restore_roadmap] error on restoring roadmap with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on restoring roadmap please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn restore_area_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path((roadmap_id, area_id)): Path<(String, String)>,
+) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Editor,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    match restore_area_with_history(
+        &roadmap_id,
+        &area_id,
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(0) => ApiError::new(
+            StatusCode::NOT_FOUND,
+            "Area not found in trash, its parent might need to be restored first",
+        )
+        .into_response(),
+        Ok(restored) => {
+            router_global_state.roadmap_hub.publish(
+                &roadmap_id,
+                RoadmapChange {
+                    connection_id: None,
+                    sequence: None,
+                    event: ServerEvent::AreaRestored {
+                        editor: user.username,
+                        area_id: area_id.clone(),
+                    },
+                },
+            );
+
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: format!("Restored {} areas", restored),
+                id: Some(area_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][restore_area] error on restoring area with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on restoring area please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+// every item of the batch as skipped, in the order of the request
+fn skipped_batch_items(batch: &AreaBatch) -> Vec<AreaBatchItemResponse> {
+    let creates = batch
+        .creates
+        .iter()
+        .enumerate()
+        .map(|(index, create)| AreaBatchItemResponse {
+            temp_id: Some(create.temp_id.clone()),
+            ..AreaBatchItemResponse::new(BatchOperation::Create, index, BatchItemStatus::Skipped)
+        });
+    let others = [
+        (BatchOperation::Update, batch.updates.len()),
+        (BatchOperation::Edge, batch.edges.len()),
+        (BatchOperation::Delete, batch.deletes.len()),
+    ]
+    .into_iter()
+    .flat_map(|(operation, count)| {
+        (0..count).map(move |index| {
+            AreaBatchItemResponse::new(operation, index, BatchItemStatus::Skipped)
+        })
+    });
+
+    creates.chain(others).collect()
+}
+
+// the response to a batch that wasn't applied because of `rejected`, the other items are skipped
+fn rejected_batch(batch: &AreaBatch, rejected: AreaBatchItemResponse) -> AreaBatchResponse {
+    let message = format!(
+        "Nothing was applied, {} {} was rejected: {}",
+        rejected.operation.as_str(),
+        rejected.index,
+        rejected.message.as_deref().unwrap_or_default()
+    );
+    let mut results = skipped_batch_items(batch);
+    if let Some(result) = results
+        .iter_mut()
+        .find(|result| result.operation == rejected.operation && result.index == rejected.index)
+    {
+        *result = AreaBatchItemResponse {
+            temp_id: result.temp_id.take(),
+            ..rejected
+        };
+    }
+
+    AreaBatchResponse {
+        is_successful: false,
+        message,
+        results,
+    }
+}
+
+// what the other editors are told of an applied item
+fn batch_item_event(editor: &str, item: AppliedBatchItem) -> ServerEvent {
+    match (item.operation, item.after) {
+        (BatchOperation::Delete, _) | (_, None) => ServerEvent::AreaDeleted {
+            editor: editor.to_string(),
+            area_id: item.area_id,
+        },
+        (BatchOperation::Edge, Some(after)) => ServerEvent::AreasLinked {
+            editor: editor.to_string(),
+            child_id: item.area_id,
+            parent_id: after.parent_id,
+            revision: after.revision,
+        },
+        (_, Some(after)) => ServerEvent::AreaUpserted {
+            editor: editor.to_string(),
+            area: after.into(),
+        },
+    }
+}
+
+/// Applies creates, updates, edges and deletes of areas all at once, nothing is applied when
+/// one of them can't be. Answers with the result of each item.
+pub async fn area_batch_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path((roadmap_id, suffix)): Path<(String, String)>,
+    batch: Result<Json<AreaBatch>, JsonRejection>,
+) -> Response<Body> {
+    // the router reads the `:batch` of `areas:batch` as a path parameter, anything else after
+    // `areas` is no route at all whatever the body
+    if suffix != ":batch" {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            &format!("No route for areas{}", suffix),
+        )
+        .into_response();
+    }
+
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Editor,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    let Json(batch) = match batch {
+        Ok(batch) => batch,
+        Err(rejection) => {
+            return ApiError::new(rejection.status(), &rejection.body_text()).into_response()
+        }
+    };
+    if batch.is_empty() || batch.len() > MAX_AREA_BATCH_ITEMS {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            &format!("A batch holds between 1 and {} items", MAX_AREA_BATCH_ITEMS),
+        )
+        .into_response();
+    }
+
+    let pool = &router_global_state.pool;
+    let (status, response) = match apply_area_batch(&roadmap_id, &user.username, &batch, pool).await
+    {
+        Ok(applied) => {
+            let mut results = Vec::with_capacity(applied.len());
+            for item in applied {
+                let mut result = AreaBatchItemResponse::new(
+                    item.operation,
+                    item.index,
+                    BatchItemStatus::Applied,
+                );
+                if item.operation == BatchOperation::Create {
+                    result.temp_id = Some(batch.creates[item.index].temp_id.clone());
+                }
+                result.area_id = Some(item.area_id.clone());
+                result.revision = item.after.as_ref().map(|area| area.revision);
+                results.push(result);
+
+                router_global_state.roadmap_hub.publish(
+                    &roadmap_id,
+                    RoadmapChange {
+                        connection_id: None,
+                        sequence: None,
+                        event: batch_item_event(&user.username, item),
+                    },
+                );
+            }
+
+            (
+                StatusCode::OK,
+                AreaBatchResponse {
+                    is_successful: true,
+                    message: format!("Applied {} items", results.len()),
+                    results,
+                },
+            )
+        }
+        Err(AreaBatchError::Database(err)) => {
+            error!(
+                "[roadmap][area_batch] error on applying batch with cause {}",
+                err
+            );
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on applying batch please try again",
+            )
+            .into_response();
+        }
+        Err(AreaBatchError::Rejected {
+            operation,
+            index,
+            code,
+            message,
+        }) => {
+            let status = match code {
+                ErrorCode::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            let rejected = AreaBatchItemResponse {
+                code: Some(code),
+                message: Some(message),
+                ..AreaBatchItemResponse::new(operation, index, BatchItemStatus::Rejected)
+            };
+            (status, rejected_batch(&batch, rejected))
+        }
+        Err(AreaBatchError::Conflict {
+            index,
+            fields,
+            current,
+        }) => {
+            let conflict = AreaBatchItemResponse {
+                message: Some(String::from("Area was changed since the base revision")),
+                fields: Some(fields),
+                area: Some(current.into()),
+                ..AreaBatchItemResponse::new(
+                    BatchOperation::Update,
+                    index,
+                    BatchItemStatus::Conflict,
+                )
+            };
+            (StatusCode::CONFLICT, rejected_batch(&batch, conflict))
+        }
+    };
+
+    Response::builder()
+        .status(status)
+        .body(Body::from(serde_json::to_string(&response).unwrap()))
+        .unwrap()
+}
+
+/// Changes made to the areas of the roadmap, latest first.
+pub async fn get_roadmap_history_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+    Query(params): Query<RoadmapHistoryParams>,
+) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Viewer,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(DEFAULT_OFFSET).max(0);
+    let filter = AreaChangeFilter {
+        area_id: params.area_id,
+        author: params.author,
+    };
+
+    match get_area_changes(
+        &roadmap_id,
+        &filter,
+        limit,
+        offset,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(changes) => {
+            let response = serde_json::to_string(&RoadmapHistoryResult {
+                changes: changes.into_iter().map(AreaChangeResponse::from).collect(),
+                limit,
+                offset,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][get_roadmap_history] error on getting history with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting roadmap history please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn publish_roadmap_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    let pool = &router_global_state.pool;
+    let errors = match validate_roadmap_for_publish(roadmap_id.clone(), pool).await {
+        Ok(errors) => errors,
+        Err(err) => {
+            error!(
+                "[roadmap][publish_roadmap] error on validating roadmap with cause {}",
+                err
+            );
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on publishing roadmap please try again",
+            )
+            .into_response();
+        }
+    };
+
+    if !errors.is_empty() {
+        let response = serde_json::to_string(&PublishRoadmapResponse {
+            is_successful: false,
+            message: String::from("Roadmap is not ready to be published"),
+            id: Some(roadmap_id),
+            errors,
+        })
+        .unwrap();
+
+        return Response::builder()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .body(Body::from(response))
+            .unwrap();
+    }
+
+    match publish_roadmap(roadmap_id.clone(), &user.username, pool).await {
+        Ok(_) => {
+            let response = serde_json::to_string(&PublishRoadmapResponse {
+                is_successful: true,
+                message: String::from("Roadmap published"),
+                id: Some(roadmap_id),
+                errors,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][publish_roadmap] error on publishing roadmap with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on publishing roadmap please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn unpublish_roadmap_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match unpublish_roadmap(
+        roadmap_id.clone(),
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(_) => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Roadmap unpublished"),
+                id: Some(roadmap_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][unpublish_roadmap] error on unpublishing roadmap with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on unpublishing roadmap please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+async fn user_roadmaps_response(
+    username: String,
+    public_only: bool,
+    params: UserRoadmapsParams,
+    router_global_state: &RouterGlobalState,
+) -> Response<Body> {
+    let user_roadmaps_query = UserRoadmapsQuery {
+        public_only,
+        sort: params.sort.unwrap_or(RoadmapSort::Created),
+        order: params.order.unwrap_or(SortOrder::Desc),
+        limit: params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        offset: params.offset.unwrap_or(DEFAULT_OFFSET).max(0),
+    };
+
+    match get_user_roadmaps(username, &user_roadmaps_query, &router_global_state.pool).await {
+        Ok(roadmaps) => {
+            let response = serde_json::to_string(&UserRoadmapsResult {
+                roadmaps: roadmaps
+                    .into_iter()
+                    .map(|roadmap| RoadmapResponse {
+                        id: roadmap.id,
+                        title: roadmap.title,
+                        description: roadmap.description,
+                    })
+                    .collect(),
+                limit: user_roadmaps_query.limit,
+                offset: user_roadmaps_query.offset,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][get_user_roadmaps] error on getting user roadmaps with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting roadmaps please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn get_my_roadmaps_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Query(params): Query<UserRoadmapsParams>,
+) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::ReadRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    user_roadmaps_response(user.username, false, params, &router_global_state).await
+}
+
+pub async fn get_user_roadmaps_router(
+    State(router_global_state): State<RouterGlobalState>,
+    Path(username): Path<String>,
+    Query(params): Query<UserRoadmapsParams>,
+) -> Response<Body> {
+    user_roadmaps_response(username, true, params, &router_global_state).await
+}
+
+pub async fn fork_roadmap_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+    Query(params): Query<RoadmapAccessParams>,
+) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::WriteRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    let pool = &router_global_state.pool;
+    // anything the user can see on the detail can be forked
+    match get_roadmap_by_id(roadmap_id.clone(), pool).await {
+        Ok(roadmap)
+            if can_view_roadmap(
+                &roadmap,
+                Some(&user.username),
+                params.share_token.as_ref(),
+                &router_global_state,
+            )
+            .await => {}
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response();
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][fork_roadmap] error on finding roadmap with cause {}",
+                err
+            );
+            return ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on forking roadmap please try again",
+            )
+            .into_response();
+        }
+    }
+
+    match fork_roadmap(roadmap_id.clone(), &user.username, pool).await {
+        Ok(fork_id) => {
+            info!(
+                "[roadmap][fork_roadmap] {} forked {} into {}",
+                user.username, roadmap_id, fork_id
+            );
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Roadmap forked"),
+                id: Some(fork_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][fork_roadmap] error on forking roadmap with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on forking roadmap please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+fn collaborators_response(collaborators: Vec<Collaborator>) -> Response<Body> {
+    let response = serde_json::to_string(&CollaboratorsResult {
+        collaborators: collaborators
+            .into_iter()
+            .map(|collaborator| CollaboratorResponse {
+                roadmap_id: collaborator.roadmap_id,
+                username: collaborator.username,
+                role: collaborator.role,
+                invited_by: collaborator.invited_by,
+                accepted_at: collaborator.accepted_at,
+                created_at: collaborator.created_at,
+            })
+            .collect(),
+    })
+    .unwrap();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(response))
+        .unwrap()
+}
+
+pub async fn get_collaborators_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Viewer,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    match get_collaborators(roadmap_id, &router_global_state.pool).await {
+        Ok(collaborators) => collaborators_response(collaborators),
+        Err(err) => {
+            error!(
+                "[roadmap][get_collaborators] error on getting collaborators with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting collaborators please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+/// Users connected to the area websocket of the roadmap, the first to join first.
+pub async fn get_editors_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) = require_roadmap_role(
+        &roadmap_id,
+        &user,
+        RoadmapRole::Viewer,
+        &router_global_state,
+    )
+    .await
+    {
+        return err.into_response();
+    }
+
+    let mut editors: Vec<EditorResponse> = Vec::new();
+    for presence in router_global_state.roadmap_hub.editors(&roadmap_id) {
+        match editors
+            .iter_mut()
+            .find(|editor| editor.username == presence.username)
+        {
+            Some(editor) => editor.connections += 1,
+            None => editors.push(EditorResponse {
+                username: presence.username,
+                connections: 1,
+                joined_at: presence.joined_at,
+            }),
+        }
+    }
+
+    let response = serde_json::to_string(&EditorsResult { editors }).unwrap();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(response))
+        .unwrap()
+}
+
+pub async fn invite_collaborator_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+    Json(request): Json<InviteCollaboratorRequest>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    if request.role == RoadmapRole::Owner {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Collaborators can only be editors or viewers, transfer the ownership instead",
+        )
+        .into_response();
+    }
+
+    if request.username == user.username {
+        return ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "The owner can't be invited to their own roadmap",
+        )
+        .into_response();
+    }
+
+    match invite_collaborator(
+        roadmap_id.clone(),
+        &request.username,
+        request.role,
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(_) => {
+            info!(
+                "[roadmap][invite_collaborator] {} invited {} to {} as {}",
+                user.username,
+                request.username,
+                roadmap_id,
+                request.role.as_str()
+            );
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Collaborator invited"),
+                id: Some(request.username),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(sqlx::Error::Database(err)) if err.is_foreign_key_violation() => {
+            ApiError::new(StatusCode::NOT_FOUND, "User not found").into_response()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][invite_collaborator] error on inviting collaborator with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on inviting collaborator please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn accept_collaboration_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::WriteRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    match accept_collaboration(
+        roadmap_id.clone(),
+        &user.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            ApiError::new(StatusCode::NOT_FOUND, "Invitation not found").into_response()
+        }
+        Ok(_) => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Invitation accepted"),
+                id: Some(roadmap_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][accept_collaboration] error on accepting invitation with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on accepting invitation please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn remove_collaborator_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path((roadmap_id, username)): Path<(String, String)>,
+) -> Response<Body> {
+    // collaborators can leave or decline an invitation on their own, anyone else needs the owner
+    if username == user.username {
+        if let Err(err) = user
+            .require_scope(TokenScope::WriteRoadmaps, &router_global_state.pool)
+            .await
+        {
+            return err.into_response();
+        }
+    } else if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match remove_collaborator(roadmap_id, &username, &router_global_state.pool).await {
+        Ok(result) if result.rows_affected() == 0 => {
+            ApiError::new(StatusCode::NOT_FOUND, "Collaborator not found").into_response()
+        }
+        Ok(_) => {
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Collaborator removed"),
+                id: Some(username),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][remove_collaborator] error on removing collaborator with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on removing collaborator please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn transfer_ownership_router(
+    State(router_global_state): State<RouterGlobalState>,
+    context: RequestContext,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+    Json(request): Json<TransferOwnershipRequest>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match transfer_roadmap_ownership(
+        roadmap_id.clone(),
+        &user.username,
+        &request.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(_) => {
+            record_audit_event(
+                AuditEvent::RoadmapOwnershipTransferred,
+                Some(&user.username),
+                Some(&roadmap_id),
+                Some(&format!("new owner {}", request.username)),
+                &context,
+                &router_global_state.pool,
+            )
+            .await;
+
+            let response = serde_json::to_string(&CreateResponse {
+                is_successful: true,
+                message: String::from("Ownership transferred"),
+                id: Some(roadmap_id),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(sqlx::Error::RowNotFound) => ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Ownership can only be transferred to a collaborator who accepted the invitation",
+        )
+        .into_response(),
+        Err(err) => {
+            error!(
+                "[roadmap][transfer_ownership] error on transferring ownership with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on transferring ownership please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn get_my_invitations_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+) -> Response<Body> {
+    if let Err(err) = user
+        .require_scope(TokenScope::ReadRoadmaps, &router_global_state.pool)
+        .await
+    {
+        return err.into_response();
+    }
+
+    match get_pending_invitations(&user.username, &router_global_state.pool).await {
+        Ok(invitations) => collaborators_response(invitations),
+        Err(err) => {
+            error!(
+                "[roadmap][get_pending_invitations] error on getting invitations with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on getting invitations please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn set_visibility_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+    Json(request): Json<SetVisibilityRequest>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match set_roadmap_visibility(
+        roadmap_id.clone(),
+        request.visibility,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(share_token) => {
+            let response = serde_json::to_string(&VisibilityResponse {
+                is_successful: true,
+                message: String::from("Roadmap visibility updated"),
+                id: Some(roadmap_id),
+                visibility: Some(request.visibility),
+                share_token,
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][set_roadmap_visibility] error on updating visibility with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on updating visibility please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn rotate_share_token_router(
+    State(router_global_state): State<RouterGlobalState>,
+    user: AuthenticatedUser,
+    Path(roadmap_id): Path<String>,
+) -> Response<Body> {
+    if let Err(err) =
+        require_roadmap_role(&roadmap_id, &user, RoadmapRole::Owner, &router_global_state).await
+    {
+        return err.into_response();
+    }
+
+    match rotate_share_token(roadmap_id.clone(), &router_global_state.pool).await {
+        Ok(share_token) => {
+            let response = serde_json::to_string(&VisibilityResponse {
+                is_successful: true,
+                message: String::from("Share link rotated, previous links no longer work"),
+                id: Some(roadmap_id),
+                visibility: None,
+                share_token: Some(share_token),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            ApiError::new(StatusCode::NOT_FOUND, "Roadmap not found").into_response()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][rotate_share_token] error on rotating share token with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on rotating share link please try again",
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn autocomplete_tags_router(
+    State(router_global_state): State<RouterGlobalState>,
+    Query(params): Query<TagAutocompleteParams>,
+) -> Response<Body> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    // an empty prefix lists the most used tags
+    let prefix = match params.prefix.as_deref().map(str::trim) {
+        None | Some("") => String::new(),
+        Some(prefix) => match normalize_tag(prefix) {
+            Some(prefix) => prefix,
+            None => {
+                let response =
+                    serde_json::to_string(&TagAutocompleteResult { tags: Vec::new() }).unwrap();
+
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from(response))
+                    .unwrap();
+            }
+        },
+    };
+
+    match autocomplete_tags(&prefix, limit, &router_global_state.pool).await {
+        Ok(tags) => {
+            let response = serde_json::to_string(&TagAutocompleteResult {
+                tags: tags
+                    .into_iter()
+                    .map(|tag| TagCountResponse {
+                        name: tag.name,
+                        roadmap_count: tag.roadmap_count,
+                    })
+                    .collect(),
+            })
+            .unwrap();
+
+            Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::from(response))
+                .unwrap()
+        }
+        Err(err) => {
+            error!(
+                "[roadmap][autocomplete_tags] error on finding tags with cause {}",
+                err
+            );
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Fail on finding tags please try again",
+            )
+            .into_response()
+        }
+    }
 }
@@ -0,0 +1,542 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+
+use crate::utils::generate_random_str;
+
+use super::{
+    add_areas, delete_area, get_area,
+    history::{add_area_change, ChangeKind, ChangeSource, NewAreaChange},
+    is_valid_parent, link_areas, move_areas,
+    protocol::{AreaSnapshot, ErrorCode},
+    update_area, validate_area, AreaField, AreaUpdate, Areas, MovedArea, DEFAULT_ID_LENGTH,
+};
+
+/// Most items a batch can hold, creates, updates, edges and deletes together.
+pub const MAX_AREA_BATCH_ITEMS: usize = 500;
+
+/// Moves received on a websocket that aren't written yet. Dragging an area sends a move for
+/// every frame, only the last position of each area is kept and they are all written at once
+/// on `flush`.
+///
+/// The batch also keeps where each dragged area started from, so a drag is logged as a single
+/// change once it's over instead of one change per tick.
+#[derive(Default)]
+pub struct MoveBatch {
+    positions: HashMap<String, (f64, f64)>,
+    drags: HashMap<String, DragState>,
+}
+
+struct DragState {
+    from: (f64, f64),
+    from_revision: i64,
+    // moved by the last flush
+    moving: bool,
+}
+
+/// An area that was dragged, with the position and revision it had before the drag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drag {
+    pub area_id: String,
+    pub from_x: f64,
+    pub from_y: f64,
+    pub from_revision: i64,
+}
+
+impl MoveBatch {
+    pub fn new() -> MoveBatch {
+        MoveBatch::default()
+    }
+
+    pub fn push(&mut self, area_id: String, x: f64, y: f64) {
+        self.positions.insert(area_id, (x, y));
+    }
+
+    /// Number of areas waiting to be written.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Whether an area is being dragged, its drag isn't logged until it's over.
+    pub fn is_dragging(&self) -> bool {
+        !self.drags.is_empty()
+    }
+
+    /// Writes the pending moves in a single statement, returns the areas moved. The moves are
+    /// dropped even when writing them fails, the next ones will carry newer positions anyway.
+    pub async fn flush(
+        &mut self,
+        roadmap_id: &String,
+        executor: impl PgExecutor<'_>,
+    ) -> Result<Vec<MovedArea>, sqlx::Error> {
+        for drag in self.drags.values_mut() {
+            drag.moving = false;
+        }
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut area_ids = Vec::with_capacity(self.len());
+        let mut xs = Vec::with_capacity(self.len());
+        let mut ys = Vec::with_capacity(self.len());
+        for (area_id, (x, y)) in self.positions.drain() {
+            area_ids.push(area_id);
+            xs.push(x);
+            ys.push(y);
+        }
+
+        let moved = move_areas(roadmap_id, &area_ids, &xs, &ys, executor).await?;
+        for area in &moved {
+            self.drags
+                .entry(area.id.clone())
+                .or_insert(DragState {
+                    from: (area.from_x, area.from_y),
+                    from_revision: area.from_revision,
+                    moving: true,
+                })
+                .moving = true;
+        }
+
+        Ok(moved)
+    }
+
+    /// Takes the drags that are over, the areas the last flush didn't move. Every drag is over
+    /// with `all`, when the editor does anything else or leaves.
+    pub fn end_drags(&mut self, all: bool) -> Vec<Drag> {
+        let mut ended = Vec::new();
+        self.drags.retain(|area_id, drag| {
+            if all || !drag.moving {
+                ended.push(Drag {
+                    area_id: area_id.clone(),
+                    from_x: drag.from.0,
+                    from_y: drag.from.1,
+                    from_revision: drag.from_revision,
+                });
+                return false;
+            }
+            true
+        });
+
+        ended
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchOperation {
+    Create,
+    Update,
+    Edge,
+    Delete,
+}
+
+impl BatchOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchOperation::Create => "create",
+            BatchOperation::Update => "update",
+            BatchOperation::Edge => "edge",
+            BatchOperation::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct BatchCreate {
+    // how the other items of the batch refer to the area
+    pub temp_id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+
+    pub x: f64,
+    pub y: f64,
+    pub estimated_hours: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct BatchUpdate {
+    pub area_id: String,
+    // same as on the websocket, fields changed since this revision are conflicts
+    pub base_revision: i64,
+    // fields changed on the area, every field when empty
+    #[serde(default)]
+    pub changed_fields: Vec<AreaField>,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+
+    pub x: f64,
+    pub y: f64,
+    pub estimated_hours: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct BatchEdge {
+    pub child_id: String,
+    // the child becomes a root area when missing
+    pub parent_id: Option<String>,
+}
+
+/// Changes to the areas of a roadmap applied all together or not at all: creates first, then
+/// updates, edges and deletes. Areas created by the batch are referred to by their `temp_id`
+/// anywhere an area id is expected, and can be created in any order.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug, Default)]
+pub struct AreaBatch {
+    #[serde(default)]
+    pub creates: Vec<BatchCreate>,
+    #[serde(default)]
+    pub updates: Vec<BatchUpdate>,
+    #[serde(default)]
+    pub edges: Vec<BatchEdge>,
+    // deleting an area deletes its descendants too, they can't be deleted again after it
+    #[serde(default)]
+    pub deletes: Vec<String>,
+}
+
+impl AreaBatch {
+    /// Number of items in the batch.
+    pub fn len(&self) -> usize {
+        self.creates.len() + self.updates.len() + self.edges.len() + self.deletes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// An item of the batch once applied, `index` is its position in its list. `before` is
+/// `None` on creations and `after` on deletions.
+#[derive(Debug, Clone)]
+pub struct AppliedBatchItem {
+    pub operation: BatchOperation,
+    pub index: usize,
+    pub area_id: String,
+    pub before: Option<Areas>,
+    pub after: Option<Areas>,
+}
+
+impl AppliedBatchItem {
+    /// How the item is logged in the history of the roadmap, `None` for updates that didn't
+    /// change anything.
+    pub fn history(&self) -> Option<NewAreaChange> {
+        let kind = match (self.operation, &self.before, &self.after) {
+            (_, Some(before), Some(after)) if before.revision == after.revision => return None,
+            (BatchOperation::Delete, _, _) | (_, _, None) => ChangeKind::Delete,
+            (BatchOperation::Create, _, _) => ChangeKind::Create,
+            _ => ChangeKind::Update,
+        };
+
+        Some(NewAreaChange {
+            area_id: self.area_id.clone(),
+            kind,
+            source: ChangeSource::Edit,
+            target_id: None,
+            before: self.before.clone().map(AreaSnapshot::from),
+            after: self.after.clone().map(AreaSnapshot::from),
+        })
+    }
+}
+
+/// Why a batch wasn't applied, nothing of it was.
+#[derive(Debug)]
+pub enum AreaBatchError {
+    Rejected {
+        operation: BatchOperation,
+        index: usize,
+        code: ErrorCode,
+        message: String,
+    },
+    /// The update was based on a stale revision and someone else changed `fields` since.
+    Conflict {
+        index: usize,
+        fields: Vec<AreaField>,
+        current: Areas,
+    },
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AreaBatchError {
+    fn from(err: sqlx::Error) -> Self {
+        AreaBatchError::Database(err)
+    }
+}
+
+fn rejected(
+    operation: BatchOperation,
+    index: usize,
+    code: ErrorCode,
+    message: &str,
+) -> AreaBatchError {
+    AreaBatchError::Rejected {
+        operation,
+        index,
+        code,
+        message: message.to_string(),
+    }
+}
+
+async fn check_batch_parent(
+    roadmap_id: &String,
+    area_id: Option<&String>,
+    parent_id: Option<&String>,
+    (operation, index): (BatchOperation, usize),
+    connection: &mut PgConnection,
+) -> Result<(), AreaBatchError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    if !is_valid_parent(roadmap_id, area_id, parent_id, connection).await? {
+        return Err(rejected(
+            operation,
+            index,
+            ErrorCode::InvalidParent,
+            "Parent has to be another area of the roadmap that isn't one of its descendants",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Applies the batch in a single transaction along with its history as changes of `author`,
+/// returns the items applied in the order of the batch. Nothing is applied when an item can't
+/// be.
+pub async fn apply_area_batch(
+    roadmap_id: &String,
+    author: &str,
+    batch: &AreaBatch,
+    pool: &PgPool,
+) -> Result<Vec<AppliedBatchItem>, AreaBatchError> {
+    // server ids of the areas created by the batch
+    let mut ids: HashMap<&String, String> = HashMap::new();
+    for (index, create) in batch.creates.iter().enumerate() {
+        if create.temp_id.is_empty() || ids.contains_key(&create.temp_id) {
+            return Err(rejected(
+                BatchOperation::Create,
+                index,
+                ErrorCode::InvalidMessage,
+                "temp_id is required and has to be unique in the batch",
+            ));
+        }
+        ids.insert(&create.temp_id, generate_random_str(DEFAULT_ID_LENGTH));
+    }
+    let resolve = |id: &String| ids.get(id).unwrap_or(id).clone();
+
+    for (index, create) in batch.creates.iter().enumerate() {
+        validate_area(&create.title, create.x, create.y, create.estimated_hours).map_err(
+            |message| {
+                rejected(
+                    BatchOperation::Create,
+                    index,
+                    ErrorCode::InvalidMessage,
+                    &message,
+                )
+            },
+        )?;
+    }
+    for (index, update) in batch.updates.iter().enumerate() {
+        validate_area(&update.title, update.x, update.y, update.estimated_hours).map_err(
+            |message| {
+                rejected(
+                    BatchOperation::Update,
+                    index,
+                    ErrorCode::InvalidMessage,
+                    &message,
+                )
+            },
+        )?;
+    }
+
+    let mut transaction = pool.begin().await?;
+    let mut applied = Vec::with_capacity(batch.len());
+
+    // parents are created before their children, whatever order they come in
+    let mut created: HashSet<&String> = HashSet::new();
+    let mut pending: Vec<usize> = (0..batch.creates.len()).collect();
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<usize>, Vec<usize>) = pending.into_iter().partition(|index| {
+            batch.creates[*index]
+                .parent_id
+                .as_ref()
+                .is_none_or(|parent_id| !ids.contains_key(parent_id) || created.contains(parent_id))
+        });
+        if ready.is_empty() {
+            return Err(rejected(
+                BatchOperation::Create,
+                waiting[0],
+                ErrorCode::InvalidParent,
+                "Areas created in the batch can't be each other's ancestors",
+            ));
+        }
+
+        for index in ready {
+            let create = &batch.creates[index];
+            let parent_id = create.parent_id.as_ref().map(resolve);
+            check_batch_parent(
+                roadmap_id,
+                None,
+                parent_id.as_ref(),
+                (BatchOperation::Create, index),
+                &mut transaction,
+            )
+            .await?;
+
+            let area = Areas {
+                id: resolve(&create.temp_id),
+                parent_id,
+                roadmap_id: roadmap_id.clone(),
+                title: create.title.clone(),
+                description: create.description.clone(),
+                x: create.x,
+                y: create.y,
+                estimated_hours: create.estimated_hours,
+                revision: 1,
+                created_at: None,
+                updated_at: None,
+            };
+            add_areas(area.clone(), &mut *transaction).await?;
+            created.insert(&create.temp_id);
+            applied.push(AppliedBatchItem {
+                operation: BatchOperation::Create,
+                index,
+                area_id: area.id.clone(),
+                before: None,
+                after: Some(area),
+            });
+        }
+        pending = waiting;
+    }
+    applied.sort_by_key(|item| item.index);
+
+    for (index, update) in batch.updates.iter().enumerate() {
+        let fields = if update.changed_fields.is_empty() {
+            AreaField::ALL.to_vec()
+        } else {
+            update.changed_fields.clone()
+        };
+        let area = Areas {
+            id: resolve(&update.area_id),
+            parent_id: update.parent_id.as_ref().map(resolve),
+            roadmap_id: roadmap_id.clone(),
+            title: update.title.clone(),
+            description: update.description.clone(),
+            x: update.x,
+            y: update.y,
+            estimated_hours: update.estimated_hours,
+            revision: update.base_revision,
+            created_at: None,
+            updated_at: None,
+        };
+        if fields.contains(&AreaField::ParentId) {
+            check_batch_parent(
+                roadmap_id,
+                Some(&area.id),
+                area.parent_id.as_ref(),
+                (BatchOperation::Update, index),
+                &mut transaction,
+            )
+            .await?;
+        }
+
+        match update_area(&area, update.base_revision, &fields, &mut *transaction).await {
+            Ok(AreaUpdate::Applied { before, area }) => applied.push(AppliedBatchItem {
+                operation: BatchOperation::Update,
+                index,
+                area_id: area.id.clone(),
+                before: Some(before),
+                after: Some(area),
+            }),
+            Ok(AreaUpdate::Conflict { fields, current }) => {
+                return Err(AreaBatchError::Conflict {
+                    index,
+                    fields,
+                    current,
+                })
+            }
+            Err(sqlx::Error::RowNotFound) => {
+                return Err(rejected(
+                    BatchOperation::Update,
+                    index,
+                    ErrorCode::NotFound,
+                    "Area not found",
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    for (index, edge) in batch.edges.iter().enumerate() {
+        let child_id = resolve(&edge.child_id);
+        let parent_id = edge.parent_id.as_ref().map(resolve);
+        let not_found = || {
+            rejected(
+                BatchOperation::Edge,
+                index,
+                ErrorCode::NotFound,
+                "Area not found",
+            )
+        };
+        check_batch_parent(
+            roadmap_id,
+            Some(&child_id),
+            parent_id.as_ref(),
+            (BatchOperation::Edge, index),
+            &mut transaction,
+        )
+        .await?;
+
+        let before = get_area(roadmap_id, &child_id, &mut *transaction)
+            .await?
+            .ok_or_else(not_found)?;
+        let revision = link_areas(roadmap_id, &child_id, parent_id.as_ref(), &mut *transaction)
+            .await?
+            .ok_or_else(not_found)?;
+        applied.push(AppliedBatchItem {
+            operation: BatchOperation::Edge,
+            index,
+            area_id: child_id,
+            after: Some(Areas {
+                parent_id,
+                revision,
+                ..before.clone()
+            }),
+            before: Some(before),
+        });
+    }
+
+    for (index, area_id) in batch.deletes.iter().enumerate() {
+        let area_id = resolve(area_id);
+        let before = get_area(roadmap_id, &area_id, &mut *transaction)
+            .await?
+            .ok_or_else(|| {
+                rejected(
+                    BatchOperation::Delete,
+                    index,
+                    ErrorCode::NotFound,
+                    "Area not found, or deleted along with an ancestor",
+                )
+            })?;
+        delete_area(roadmap_id.clone(), area_id.clone(), &mut *transaction).await?;
+        applied.push(AppliedBatchItem {
+            operation: BatchOperation::Delete,
+            index,
+            area_id,
+            before: Some(before),
+            after: None,
+        });
+    }
+
+    for change in applied.iter().filter_map(AppliedBatchItem::history) {
+        add_area_change(roadmap_id, author, &change, &mut *transaction).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(applied)
+}
@@ -0,0 +1,203 @@
+//! Encoding of the area websocket frames, picked with the `Sec-WebSocket-Protocol` header when
+//! connecting. Clients list the subprotocols they understand and the server answers with the
+//! one it prefers:
+//!
+//! - `via-discendi.msgpack+deflate`, deflated MessagePack in binary frames
+//! - `via-discendi.msgpack`, MessagePack in binary frames
+//! - `via-discendi.json+deflate`, deflated JSON in binary frames
+//! - `via-discendi.json`, JSON in text frames, also used when no subprotocol is asked for
+//!
+//! Messages are the same whatever the encoding, MessagePack maps use the JSON field names.
+//!
+//! The `permessage-deflate` extension isn't supported: neither axum nor tungstenite implement it
+//! and they reject frames with the reserved bit it relies on. Compression is negotiated as part
+//! of the subprotocol instead, each frame being a raw deflate stream. Clients offering
+//! `permessage-deflate` are answered without it and fall back to uncompressed frames. Frames are
+//! held to `WEBSOCKET_MAX_MESSAGE_BYTES` once inflated as well.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, Read, Write},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub const JSON_SUBPROTOCOL: &str = "via-discendi.json";
+pub const JSON_DEFLATE_SUBPROTOCOL: &str = "via-discendi.json+deflate";
+pub const MSGPACK_SUBPROTOCOL: &str = "via-discendi.msgpack";
+pub const MSGPACK_DEFLATE_SUBPROTOCOL: &str = "via-discendi.msgpack+deflate";
+// in order of preference
+pub const SUBPROTOCOLS: [&str; 4] = [
+    MSGPACK_DEFLATE_SUBPROTOCOL,
+    MSGPACK_SUBPROTOCOL,
+    JSON_DEFLATE_SUBPROTOCOL,
+    JSON_SUBPROTOCOL,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Codec {
+    pub format: WireFormat,
+    pub compressed: bool,
+    // a compressed frame can't inflate past this, so a small frame can't exhaust the memory
+    pub max_inflated_size: usize,
+}
+
+/// A websocket data frame. Kept apart from the websocket libraries so the server and the test
+/// clients share the same codec.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    // text frame on a binary subprotocol or the other way around
+    UnexpectedFrame,
+    Inflate(io::Error),
+    // the limit it inflates past
+    TooLarge(usize),
+    Json(serde_json::Error),
+    MessagePack(rmp_serde::decode::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::UnexpectedFrame => {
+                write!(f, "frame type doesn't match the negotiated subprotocol")
+            }
+            CodecError::Inflate(err) => write!(f, "invalid deflate stream: {}", err),
+            CodecError::TooLarge(max_inflated_size) => write!(
+                f,
+                "frame inflates past the limit of {} bytes",
+                max_inflated_size
+            ),
+            CodecError::Json(err) => write!(f, "{}", err),
+            CodecError::MessagePack(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Codec {
+    pub const JSON: Codec = Codec {
+        format: WireFormat::Json,
+        compressed: false,
+        max_inflated_size: usize::MAX,
+    };
+
+    /// Codec for the comma separated subprotocols requested by the client, our preferred one
+    /// when several match. JSON when the client doesn't ask for any, `None` when none of them
+    /// is supported. Frames can inflate to any size until `with_max_inflated_size`.
+    pub fn negotiate(requested: Option<&str>) -> Option<Codec> {
+        let Some(requested) = requested else {
+            return Some(Codec::JSON);
+        };
+
+        SUBPROTOCOLS
+            .into_iter()
+            .find(|subprotocol| {
+                requested
+                    .split(',')
+                    .any(|requested| requested.trim() == *subprotocol)
+            })
+            .and_then(Codec::from_subprotocol)
+    }
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<Codec> {
+        let (format, compressed) = match subprotocol {
+            JSON_SUBPROTOCOL => (WireFormat::Json, false),
+            JSON_DEFLATE_SUBPROTOCOL => (WireFormat::Json, true),
+            MSGPACK_SUBPROTOCOL => (WireFormat::MessagePack, false),
+            MSGPACK_DEFLATE_SUBPROTOCOL => (WireFormat::MessagePack, true),
+            _ => return None,
+        };
+
+        Some(Codec {
+            format,
+            compressed,
+            max_inflated_size: usize::MAX,
+        })
+    }
+
+    pub fn with_max_inflated_size(self, max_inflated_size: usize) -> Codec {
+        Codec {
+            max_inflated_size,
+            ..self
+        }
+    }
+
+    pub fn subprotocol(&self) -> &'static str {
+        match (self.format, self.compressed) {
+            (WireFormat::Json, false) => JSON_SUBPROTOCOL,
+            (WireFormat::Json, true) => JSON_DEFLATE_SUBPROTOCOL,
+            (WireFormat::MessagePack, false) => MSGPACK_SUBPROTOCOL,
+            (WireFormat::MessagePack, true) => MSGPACK_DEFLATE_SUBPROTOCOL,
+        }
+    }
+
+    /// Whether frames are sent as text, only uncompressed JSON is.
+    pub fn is_text(&self) -> bool {
+        self.format == WireFormat::Json && !self.compressed
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Frame {
+        let data = match self.format {
+            WireFormat::Json => serde_json::to_vec(value).unwrap(),
+            WireFormat::MessagePack => rmp_serde::to_vec_named(value).unwrap(),
+        };
+
+        if self.is_text() {
+            // serde_json only writes valid utf-8
+            return Frame::Text(String::from_utf8(data).unwrap());
+        }
+        if !self.compressed {
+            return Frame::Binary(data);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        // writing to a vec can't fail
+        encoder.write_all(&data).unwrap();
+        Frame::Binary(encoder.finish().unwrap())
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, frame: &Frame) -> Result<T, CodecError> {
+        let data = match frame {
+            Frame::Text(text) if self.is_text() => Cow::Borrowed(text.as_bytes()),
+            Frame::Binary(data) if !self.is_text() && self.compressed => {
+                Cow::Owned(inflate(data, self.max_inflated_size)?)
+            }
+            Frame::Binary(data) if !self.is_text() => Cow::Borrowed(data.as_slice()),
+            _ => return Err(CodecError::UnexpectedFrame),
+        };
+
+        match self.format {
+            WireFormat::Json => serde_json::from_slice(&data).map_err(CodecError::Json),
+            WireFormat::MessagePack => {
+                rmp_serde::from_slice(&data).map_err(CodecError::MessagePack)
+            }
+        }
+    }
+}
+
+fn inflate(data: &[u8], max_inflated_size: usize) -> Result<Vec<u8>, CodecError> {
+    let mut inflated = Vec::new();
+    DeflateDecoder::new(data)
+        .take((max_inflated_size as u64).saturating_add(1))
+        .read_to_end(&mut inflated)
+        .map_err(CodecError::Inflate)?;
+
+    if inflated.len() > max_inflated_size {
+        return Err(CodecError::TooLarge(max_inflated_size));
+    }
+
+    Ok(inflated)
+}
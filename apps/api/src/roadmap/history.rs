@@ -0,0 +1,316 @@
+//! Append only log of the changes made to the areas of a roadmap, the edit timeline of the
+//! roadmap and what undo and redo are worked out from.
+//!
+//! Every user has their own undo and redo stacks on each roadmap. Undoing applies the inverse
+//! of the user's latest change that isn't undone yet, redoing applies the inverse of their
+//! latest undo that isn't redone yet, as long as they didn't make a new change since. Undos
+//! and redos are changes themselves, logged with the change they undo or redo as their target.
+//! A change that can't be reverted anymore, its area deleted or changed again by someone else,
+//! is marked skipped so the next undo or redo moves past it.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{query, query_as, PgExecutor, PgPool};
+
+use super::{protocol::AreaSnapshot, AreaField};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    // the area and its descendants were moved to the trash
+    Delete,
+    // the area and its descendants were restored from the trash
+    Restore,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Restore => "restore",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ChangeKind> {
+        [
+            ChangeKind::Create,
+            ChangeKind::Update,
+            ChangeKind::Delete,
+            ChangeKind::Restore,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == value)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSource {
+    Edit,
+    Undo,
+    Redo,
+    // marks its target as not revertable, not a change of the area
+    Skip,
+}
+
+impl ChangeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSource::Edit => "edit",
+            ChangeSource::Undo => "undo",
+            ChangeSource::Redo => "redo",
+            ChangeSource::Skip => "skip",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ChangeSource> {
+        [
+            ChangeSource::Edit,
+            ChangeSource::Undo,
+            ChangeSource::Redo,
+            ChangeSource::Skip,
+        ]
+        .into_iter()
+        .find(|source| source.as_str() == value)
+    }
+}
+
+/// A change to log, `before` is `None` on creations and `after` on deletions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAreaChange {
+    pub area_id: String,
+    pub kind: ChangeKind,
+    pub source: ChangeSource,
+    // the change undone or redone
+    pub target_id: Option<i64>,
+    pub before: Option<AreaSnapshot>,
+    pub after: Option<AreaSnapshot>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AreaChange {
+    pub id: i64,
+    pub area_id: String,
+    pub author: String,
+    pub kind: ChangeKind,
+    pub source: ChangeSource,
+    pub target_id: Option<i64>,
+    pub before: Option<AreaSnapshot>,
+    pub after: Option<AreaSnapshot>,
+    pub created_at: NaiveDateTime,
+}
+
+// a row of AREA_CHANGES as it is stored
+struct AreaChangeRow {
+    id: i64,
+    area_id: String,
+    author: String,
+    kind: String,
+    source: String,
+    target_id: Option<i64>,
+    before: Option<String>,
+    after: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct AreaChangeFilter {
+    pub area_id: Option<String>,
+    pub author: Option<String>,
+}
+
+fn decode_snapshot(snapshot: Option<String>) -> Result<Option<AreaSnapshot>, sqlx::Error> {
+    snapshot
+        .map(|snapshot| serde_json::from_str(&snapshot))
+        .transpose()
+        .map_err(|err| sqlx::Error::Decode(Box::new(err)))
+}
+
+impl TryFrom<AreaChangeRow> for AreaChange {
+    type Error = sqlx::Error;
+
+    fn try_from(row: AreaChangeRow) -> Result<Self, Self::Error> {
+        let kind = ChangeKind::parse(&row.kind).ok_or_else(|| {
+            sqlx::Error::Decode(format!("unknown change kind {}", row.kind).into())
+        })?;
+        let source = ChangeSource::parse(&row.source).ok_or_else(|| {
+            sqlx::Error::Decode(format!("unknown change source {}", row.source).into())
+        })?;
+
+        Ok(AreaChange {
+            id: row.id,
+            area_id: row.area_id,
+            author: row.author,
+            kind,
+            source,
+            target_id: row.target_id,
+            before: decode_snapshot(row.before)?,
+            after: decode_snapshot(row.after)?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// Fields that differ between the two snapshots of an area.
+pub fn changed_fields(before: &AreaSnapshot, after: &AreaSnapshot) -> Vec<AreaField> {
+    AreaField::ALL
+        .into_iter()
+        .filter(|field| match field {
+            AreaField::ParentId => before.parent_id != after.parent_id,
+            AreaField::Title => before.title != after.title,
+            AreaField::Description => before.description != after.description,
+            AreaField::Position => before.x != after.x || before.y != after.y,
+            AreaField::EstimatedHours => before.estimated_hours != after.estimated_hours,
+        })
+        .collect()
+}
+
+/// Logs the change, returns its id. Meant to run in the transaction making the change so a
+/// change is never made without being logged.
+pub async fn add_area_change(
+    roadmap_id: &str,
+    author: &str,
+    change: &NewAreaChange,
+    executor: impl PgExecutor<'_>,
+) -> Result<i64, sqlx::Error> {
+    // AreaSnapshot only holds plain values
+    let encode = |snapshot: &Option<AreaSnapshot>| {
+        snapshot
+            .as_ref()
+            .map(|snapshot| serde_json::to_string(snapshot).unwrap())
+    };
+
+    let record = query!(
+        r#"
+            INSERT INTO AREA_CHANGES
+                (ROADMAP_ID, AREA_ID, AUTHOR, KIND, SOURCE, TARGET_ID, BEFORE, AFTER)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7::TEXT::JSONB, $8::TEXT::JSONB)
+            RETURNING ID
+        "#,
+        roadmap_id,
+        change.area_id,
+        author,
+        change.kind.as_str(),
+        change.source.as_str(),
+        change.target_id,
+        encode(&change.before),
+        encode(&change.after)
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.id)
+}
+
+/// Changes of the roadmap, latest first. Skip markers aren't changes and are left out.
+pub async fn get_area_changes(
+    roadmap_id: &str,
+    filter: &AreaChangeFilter,
+    limit: i64,
+    offset: i64,
+    pool: &PgPool,
+) -> Result<Vec<AreaChange>, sqlx::Error> {
+    query_as!(
+        AreaChangeRow,
+        r#"
+            SELECT
+                ID, AREA_ID, AUTHOR, KIND, SOURCE, TARGET_ID,
+                BEFORE::TEXT AS "before", AFTER::TEXT AS "after", CREATED_AT
+            FROM AREA_CHANGES
+            WHERE
+                ROADMAP_ID = $1 AND
+                SOURCE <> 'skip' AND
+                ($2::VARCHAR IS NULL OR AREA_ID = $2) AND
+                ($3::VARCHAR IS NULL OR AUTHOR = $3)
+            ORDER BY ID DESC
+            LIMIT $4
+            OFFSET $5
+        "#,
+        roadmap_id,
+        filter.area_id,
+        filter.author,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(AreaChange::try_from)
+    .collect()
+}
+
+/// Latest change of `author` that isn't undone or skipped, edits and redos can be undone.
+pub async fn find_undo_target(
+    roadmap_id: &str,
+    author: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<AreaChange>, sqlx::Error> {
+    query_as!(
+        AreaChangeRow,
+        r#"
+            SELECT
+                ID, AREA_ID, AUTHOR, KIND, SOURCE, TARGET_ID,
+                BEFORE::TEXT AS "before", AFTER::TEXT AS "after", CREATED_AT
+            FROM AREA_CHANGES C
+            WHERE
+                ROADMAP_ID = $1 AND
+                AUTHOR = $2 AND
+                SOURCE IN ('edit', 'redo') AND
+                NOT EXISTS (
+                    SELECT 1 FROM AREA_CHANGES U
+                    WHERE U.TARGET_ID = C.ID AND U.SOURCE IN ('undo', 'skip')
+                )
+            ORDER BY ID DESC
+            LIMIT 1
+        "#,
+        roadmap_id,
+        author
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(AreaChange::try_from)
+    .transpose()
+}
+
+/// Latest undo of `author` that isn't redone or skipped, a new edit empties the redo stack so only the
+/// undos made after the last edit count.
+pub async fn find_redo_target(
+    roadmap_id: &str,
+    author: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<AreaChange>, sqlx::Error> {
+    query_as!(
+        AreaChangeRow,
+        r#"
+            SELECT
+                ID, AREA_ID, AUTHOR, KIND, SOURCE, TARGET_ID,
+                BEFORE::TEXT AS "before", AFTER::TEXT AS "after", CREATED_AT
+            FROM AREA_CHANGES C
+            WHERE
+                ROADMAP_ID = $1 AND
+                AUTHOR = $2 AND
+                SOURCE = 'undo' AND
+                ID > COALESCE((
+                    SELECT MAX(ID) FROM AREA_CHANGES
+                    WHERE ROADMAP_ID = $1 AND AUTHOR = $2 AND SOURCE = 'edit'
+                ), 0) AND
+                NOT EXISTS (
+                    SELECT 1 FROM AREA_CHANGES R
+                    WHERE R.TARGET_ID = C.ID AND R.SOURCE IN ('redo', 'skip')
+                )
+            ORDER BY ID DESC
+            LIMIT 1
+        "#,
+        roadmap_id,
+        author
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(AreaChange::try_from)
+    .transpose()
+}
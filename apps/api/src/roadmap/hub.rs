@@ -0,0 +1,287 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    watch,
+};
+
+use crate::utils::generate_random_str;
+
+use super::protocol::{EditorPresence, ServerEvent};
+
+// changes a slow client can fall behind on before it starts missing them
+const CHANNEL_CAPACITY: usize = 256;
+// changes kept for reconnecting clients to catch up on
+pub const REPLAY_CAPACITY: usize = 1024;
+const STREAM_ID_LENGTH: u8 = 16;
+// how long a channel is kept once its last editor left, the default idle timeout and ping
+// interval of the websockets together
+const DEFAULT_IDLE_GRACE: Duration = Duration::from_secs(120);
+
+/// A change committed to a roadmap, `connection_id` is the websocket it came from, if any, so
+/// that connection doesn't get its own change back. `sequence` is given by the hub when the
+/// change is published, presence and cursors don't have one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadmapChange {
+    pub connection_id: Option<String>,
+    pub sequence: Option<u64>,
+    pub event: ServerEvent,
+}
+
+/// Where a reconnecting client left off, the stream it was on and the last sequence it got.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResumePoint {
+    pub stream_id: String,
+    pub sequence: u64,
+}
+
+/// The roadmap as a new subscription finds it.
+#[derive(Debug)]
+pub struct JoinedRoadmap {
+    // the other websockets already connected
+    pub editors: Vec<EditorPresence>,
+    pub stream_id: String,
+    // last sequence published, the subscription gets every change after it
+    pub sequence: u64,
+    // changes after the resume point, `None` when not resuming or when they aren't all kept
+    // anymore
+    pub missed: Option<Vec<RoadmapChange>>,
+}
+
+struct RoadmapChannel {
+    sender: broadcast::Sender<RoadmapChange>,
+    // in the order they joined
+    editors: Vec<EditorPresence>,
+    // sequences restart with every channel, the stream id tells them apart
+    stream_id: String,
+    sequence: u64,
+    // the last published changes, oldest first
+    replay: VecDeque<RoadmapChange>,
+    // when the last editor left, `None` while someone is connected
+    idle_since: Option<Instant>,
+}
+
+impl RoadmapChannel {
+    fn missed_since(&self, resume: &ResumePoint) -> Option<Vec<RoadmapChange>> {
+        if resume.stream_id != self.stream_id || resume.sequence > self.sequence {
+            return None;
+        }
+        if resume.sequence == self.sequence {
+            return Some(Vec::new());
+        }
+
+        // the change right after the resume point must still be kept
+        let oldest = self.replay.front()?.sequence?;
+        if oldest > resume.sequence + 1 {
+            return None;
+        }
+
+        Some(
+            self.replay
+                .iter()
+                .filter(|change| change.sequence > Some(resume.sequence))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Fans out the changes of a roadmap to every websocket editing it and keeps track of who is
+/// connected. Changes are numbered and the last ones kept so a client reconnecting can catch up
+/// on what it missed. A channel is opened when someone connects to the roadmap and kept for
+/// `idle_grace` after the last editor left, so even a lone editor losing its connection can
+/// resume. Changes published when there is no channel are dropped.
+#[derive(Clone)]
+pub struct RoadmapHub {
+    channels: Arc<Mutex<HashMap<String, RoadmapChannel>>>,
+    // subscriptions still alive, waited on when shutting down
+    subscriptions: Arc<watch::Sender<usize>>,
+    idle_grace: Duration,
+}
+
+impl Default for RoadmapHub {
+    fn default() -> RoadmapHub {
+        RoadmapHub::new(DEFAULT_IDLE_GRACE)
+    }
+}
+
+impl RoadmapHub {
+    /// Channels are kept for `idle_grace` once no one is connected, it should be at least as
+    /// long as a websocket can take to notice its connection was lost.
+    pub fn new(idle_grace: Duration) -> RoadmapHub {
+        RoadmapHub {
+            channels: Arc::default(),
+            subscriptions: Arc::new(watch::Sender::new(0)),
+            idle_grace,
+        }
+    }
+
+    // drops the channels no one came back to in time, called with the lock held
+    fn remove_idle_channels(&self, channels: &mut HashMap<String, RoadmapChannel>) {
+        channels.retain(|_, channel| {
+            channel
+                .idle_since
+                .is_none_or(|idle_since| idle_since.elapsed() < self.idle_grace)
+        });
+    }
+
+    /// Subscribes to the changes of the roadmap and announces `editor` to everyone else on it.
+    /// Also returns the state of the roadmap, taken at the same time so no change, join or leave
+    /// is missed in between, with the changes missed since `resume` when it is given.
+    pub fn subscribe(
+        &self,
+        roadmap_id: &str,
+        editor: EditorPresence,
+        resume: Option<&ResumePoint>,
+    ) -> (RoadmapSubscription, JoinedRoadmap) {
+        let mut channels = self.channels.lock().unwrap();
+        self.remove_idle_channels(&mut channels);
+        let channel = channels
+            .entry(roadmap_id.to_string())
+            .or_insert_with(|| RoadmapChannel {
+                sender: broadcast::channel(CHANNEL_CAPACITY).0,
+                editors: Vec::new(),
+                stream_id: generate_random_str(STREAM_ID_LENGTH),
+                sequence: 0,
+                replay: VecDeque::new(),
+                idle_since: None,
+            });
+        channel.idle_since = None;
+        // announced before subscribing so the editor doesn't hear about itself
+        let _ = channel.sender.send(RoadmapChange {
+            connection_id: Some(editor.connection_id.clone()),
+            sequence: None,
+            event: ServerEvent::EditorJoined {
+                editor: editor.clone(),
+            },
+        });
+        let receiver = channel.sender.subscribe();
+        let joined = JoinedRoadmap {
+            editors: channel.editors.clone(),
+            stream_id: channel.stream_id.clone(),
+            sequence: channel.sequence,
+            missed: resume.and_then(|resume| channel.missed_since(resume)),
+        };
+        channel.editors.push(editor.clone());
+        self.subscriptions.send_modify(|count| *count += 1);
+
+        let subscription = RoadmapSubscription {
+            hub: self.clone(),
+            roadmap_id: roadmap_id.to_string(),
+            editor,
+            receiver: Some(receiver),
+        };
+
+        (subscription, joined)
+    }
+
+    /// Numbers the change and sends it to everyone on the roadmap, returns its sequence. `None`
+    /// when the roadmap has no channel, no one connected to it lately.
+    pub fn publish(&self, roadmap_id: &str, mut change: RoadmapChange) -> Option<u64> {
+        let mut channels = self.channels.lock().unwrap();
+        self.remove_idle_channels(&mut channels);
+        let channel = channels.get_mut(roadmap_id)?;
+
+        channel.sequence += 1;
+        change.sequence = Some(channel.sequence);
+        if channel.replay.len() == REPLAY_CAPACITY {
+            channel.replay.pop_front();
+        }
+        channel.replay.push_back(change.clone());
+        // fails only when every receiver is gone, which is the same as no one listening
+        let _ = channel.sender.send(change);
+
+        Some(channel.sequence)
+    }
+
+    /// Sends the change to everyone on the roadmap without numbering nor keeping it, for what
+    /// only matters as it happens like cursors.
+    pub fn relay(&self, roadmap_id: &str, change: RoadmapChange) {
+        if let Some(channel) = self.channels.lock().unwrap().get(roadmap_id) {
+            let _ = channel.sender.send(change);
+        }
+    }
+
+    /// Websockets connected to the roadmap, in the order they joined.
+    pub fn editors(&self, roadmap_id: &str) -> Vec<EditorPresence> {
+        self.channels
+            .lock()
+            .unwrap()
+            .get(roadmap_id)
+            .map_or(Vec::new(), |channel| channel.editors.clone())
+    }
+
+    /// Number of roadmaps with at least one subscriber.
+    #[cfg(test)]
+    pub fn active_roadmaps(&self) -> usize {
+        self.channels
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|channel| channel.idle_since.is_none())
+            .count()
+    }
+
+    /// Drops every channel, subscriptions see it as closed and their websockets wrap up.
+    pub fn close(&self) {
+        self.channels.lock().unwrap().clear();
+    }
+
+    /// Waits for every subscription to be dropped, meant to be used after `close`.
+    pub async fn wait_for_subscriptions(&self) {
+        let mut subscriptions = self.subscriptions.subscribe();
+        // the sender lives as long as the hub
+        let _ = subscriptions.wait_for(|count| *count == 0).await;
+    }
+}
+
+/// Changes of a roadmap. Dropping it announces the editor left, the channel is left for the
+/// hub to remove after its grace period when it was the last subscription.
+pub struct RoadmapSubscription {
+    hub: RoadmapHub,
+    roadmap_id: String,
+    editor: EditorPresence,
+    // only taken when dropped
+    receiver: Option<broadcast::Receiver<RoadmapChange>>,
+}
+
+impl RoadmapSubscription {
+    pub async fn recv(&mut self) -> Result<RoadmapChange, RecvError> {
+        self.receiver.as_mut().unwrap().recv().await
+    }
+}
+
+impl Drop for RoadmapSubscription {
+    fn drop(&mut self) {
+        self.hub.subscriptions.send_modify(|count| *count -= 1);
+
+        // the receiver is dropped while holding the lock so a concurrent subscribe can't pick up
+        // a channel that is about to be removed
+        let mut channels = self.hub.channels.lock().unwrap();
+        drop(self.receiver.take());
+
+        let Some(channel) = channels.get_mut(&self.roadmap_id) else {
+            return;
+        };
+        channel
+            .editors
+            .retain(|editor| editor.connection_id != self.editor.connection_id);
+
+        if channel.sender.receiver_count() == 0 {
+            channel.idle_since = Some(Instant::now());
+            return;
+        }
+
+        let _ = channel.sender.send(RoadmapChange {
+            connection_id: Some(self.editor.connection_id.clone()),
+            sequence: None,
+            event: ServerEvent::EditorLeft {
+                editor: self.editor.clone(),
+            },
+        });
+    }
+}
@@ -1,10 +1,39 @@
+use std::collections::HashMap;
+
 use chrono::NaiveDateTime;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgQueryResult;
-use sqlx::PgPool;
 use sqlx::{self, query};
+use sqlx::{Acquire, PgConnection, PgExecutor, PgPool, Postgres};
+
+use crate::{config::TrashConfig, utils::generate_random_str};
+
+use self::{
+    history::{add_area_change, ChangeKind, ChangeSource, NewAreaChange},
+    protocol::AreaSnapshot,
+};
 
 pub mod api;
+pub mod batch;
+pub mod codec;
+pub mod history;
+pub mod hub;
+pub mod protocol;
+pub mod rate_limit;
+pub mod tag;
 mod test;
+pub mod websocket;
+
+const DEFAULT_ID_LENGTH: u8 = 12;
+const SHARE_TOKEN_LENGTH: u8 = 32;
+// ROADMAPS.CONTENT_LANGUAGE is VARCHAR(16)
+const MAX_CONTENT_LANGUAGE_LENGTH: usize = 16;
+const MAX_PREREQUISITE_SUMMARY_LENGTH: usize = 2000;
+// a year of full time study, anything above is most likely a typo
+const MAX_ESTIMATED_HOURS: f64 = 2000.0;
+// ROADMAP_AREAS.TITLE is VARCHAR(32)
+const MAX_AREA_TITLE_LENGTH: usize = 32;
 
 #[derive(Debug, Clone)]
 pub struct Roadmaps {
@@ -28,18 +57,221 @@ pub struct Areas {
 
     x: f64,
     y: f64,
+    estimated_hours: Option<f64>,
+    // ignored when adding, new areas start at 1
+    revision: i64,
 
     created_at: Option<NaiveDateTime>,
     updated_at: Option<NaiveDateTime>,
 }
 
+/// Fields of an area that are versioned on their own, `x` and `y` together are the position.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AreaField {
+    ParentId,
+    Title,
+    Description,
+    Position,
+    EstimatedHours,
+}
+
+impl AreaField {
+    pub const ALL: [AreaField; 5] = [
+        AreaField::ParentId,
+        AreaField::Title,
+        AreaField::Description,
+        AreaField::Position,
+        AreaField::EstimatedHours,
+    ];
+}
+
+#[derive(Debug)]
+pub enum AreaUpdate {
+    /// The change was merged, `area` is the area as it is now and `before` as it was before
+    /// the change.
+    Applied { before: Areas, area: Areas },
+    /// Someone else changed `fields` after the revision the change was based on, nothing was
+    /// changed. `current` is the area as it is now.
+    Conflict {
+        fields: Vec<AreaField>,
+        current: Areas,
+    },
+}
+
 pub struct RoadmapWithAreas {
     roadmap_id: String,
     roadmap_title: String,
     roadmap_description: Option<String>,
+    publisher: String,
+    published: bool,
+    published_at: Option<NaiveDateTime>,
+    forked_from: Option<String>,
+    fork_count: i64,
+    visibility: RoadmapVisibility,
+    share_token: Option<String>,
+    tags: Vec<String>,
+    metadata: RoadmapMetadata,
+    // sum of the estimates of the areas
+    total_estimated_hours: f64,
     areas: Vec<Areas>,
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+impl Difficulty {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "beginner",
+            Difficulty::Intermediate => "intermediate",
+            Difficulty::Advanced => "advanced",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Difficulty> {
+        [
+            Difficulty::Beginner,
+            Difficulty::Intermediate,
+            Difficulty::Advanced,
+        ]
+        .into_iter()
+        .find(|difficulty| difficulty.as_str() == value)
+    }
+}
+
+/// What learners look at when choosing a roadmap, every field is optional.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug, Default)]
+pub struct RoadmapMetadata {
+    pub difficulty: Option<Difficulty>,
+    // the author's own estimate, overrides the sum of the areas in search
+    pub estimated_hours: Option<f64>,
+    pub content_language: Option<String>,
+    pub prerequisite_summary: Option<String>,
+}
+
+impl RoadmapMetadata {
+    /// Lowercases the language and trims the text fields, the error is meant to be shown to
+    /// the user.
+    pub fn normalize(self) -> Result<RoadmapMetadata, String> {
+        if let Some(estimated_hours) = self.estimated_hours {
+            validate_estimated_hours(estimated_hours)?;
+        }
+
+        let content_language = match self.content_language.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(language) => Some(normalize_content_language(language).ok_or(format!(
+                "Invalid content language \"{}\", expected a language tag like en or pt-BR",
+                language
+            ))?),
+        };
+
+        let prerequisite_summary = match self.prerequisite_summary.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(summary) if summary.chars().count() > MAX_PREREQUISITE_SUMMARY_LENGTH => {
+                return Err(format!(
+                    "Prerequisite summary can be at most {} characters",
+                    MAX_PREREQUISITE_SUMMARY_LENGTH
+                ));
+            }
+            Some(summary) => Some(summary.to_string()),
+        };
+
+        Ok(RoadmapMetadata {
+            difficulty: self.difficulty,
+            estimated_hours: self.estimated_hours,
+            content_language,
+            prerequisite_summary,
+        })
+    }
+}
+
+fn validate_estimated_hours(estimated_hours: f64) -> Result<(), String> {
+    if !estimated_hours.is_finite() || !(0.0..=MAX_ESTIMATED_HOURS).contains(&estimated_hours) {
+        return Err(format!(
+            "Estimated hours have to be between 0 and {}",
+            MAX_ESTIMATED_HOURS
+        ));
+    }
+
+    Ok(())
+}
+
+// NaN and infinities can't be sent as JSON but can be as MessagePack
+fn validate_position(x: f64, y: f64) -> Result<(), String> {
+    if !x.is_finite() || !y.is_finite() {
+        return Err(String::from("Positions have to be finite numbers"));
+    }
+
+    Ok(())
+}
+
+/// Checks the fields of an area the database would reject, or store but not give back, the
+/// error is meant to be shown to the user.
+fn validate_area(title: &str, x: f64, y: f64, estimated_hours: Option<f64>) -> Result<(), String> {
+    if title.trim().is_empty() || title.chars().count() > MAX_AREA_TITLE_LENGTH {
+        return Err(format!(
+            "Area titles are between 1 and {} characters",
+            MAX_AREA_TITLE_LENGTH
+        ));
+    }
+
+    validate_position(x, y)?;
+    estimated_hours.map_or(Ok(()), validate_estimated_hours)
+}
+
+// BCP 47 style tags, only the shape is checked: "en", "pt-br", "zh-hant-tw"
+pub fn normalize_content_language(language: &str) -> Option<String> {
+    let language = language.to_lowercase();
+    let mut subtags = language.split('-');
+    let primary = subtags.next()?;
+
+    let is_valid = language.len() <= MAX_CONTENT_LANGUAGE_LENGTH
+        && (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && subtags.all(|subtag| {
+            (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+
+    is_valid.then_some(language)
+}
+
+/// Who can see a published roadmap besides its owner and collaborators.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RoadmapVisibility {
+    // owner and collaborators only
+    Private,
+    // anyone with the share link, but not listed or searchable
+    Unlisted,
+    Public,
+}
+
+impl RoadmapVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoadmapVisibility::Private => "private",
+            RoadmapVisibility::Unlisted => "unlisted",
+            RoadmapVisibility::Public => "public",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RoadmapVisibility> {
+        [
+            RoadmapVisibility::Private,
+            RoadmapVisibility::Unlisted,
+            RoadmapVisibility::Public,
+        ]
+        .into_iter()
+        .find(|visibility| visibility.as_str() == value)
+    }
+}
+
 pub struct UserRoadmaps {
     user_id: String,
     roadmap_id: String,
@@ -72,7 +304,10 @@ pub struct RoadmapBookmarkers {
     updated_at: Option<NaiveDateTime>,
 }
 
-async fn add_roadmap(roadmap: Roadmaps, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
+async fn add_roadmap(
+    roadmap: Roadmaps,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
     let query = query!(
         r#"
             INSERT INTO ROADMAPS 
@@ -86,37 +321,136 @@ async fn add_roadmap(roadmap: Roadmaps, pool: &PgPool) -> Result<PgQueryResult,
         roadmap.publisher,
         roadmap.published
     )
-    .execute(pool)
+    .execute(executor)
     .await;
 
     query
 }
 
-async fn update_roadmap(roadmap: Roadmaps, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
+// `roadmap.publisher` is the user making the change, either the owner or an editor
+async fn update_roadmap(
+    roadmap: Roadmaps,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
     let query = query!(
         r#"
             UPDATE ROADMAPS
                 SET TITLE = $1,
                 DESCRIPTION = $2,
-                PUBLISHED = $3,
                 UPDATED_AT = NOW()
             WHERE 
-                ID = $4;
+                ID = $3 AND
+                (
+                    PUBLISHER = $4 OR
+                    EXISTS (
+                        SELECT 1 FROM ROADMAP_COLLABORATORS C
+                        WHERE
+                            C.ROADMAP_ID = ROADMAPS.ID AND
+                            C.USERNAME = $4 AND
+                            C.ROLE = 'editor' AND
+                            C.ACCEPTED_AT IS NOT NULL
+                    )
+                ) AND
+                DELETED_AT IS NULL;
         "#,
         roadmap.title,
         roadmap.description,
-        roadmap.published,
-        roadmap.id
+        roadmap.id,
+        roadmap.publisher
     )
-    .execute(pool)
+    .execute(executor)
     .await;
 
     query
 }
 
+// tags and metadata reference the roadmap so they are saved after it, `None` keeps them as is
+async fn save_roadmap_details(
+    roadmap_id: &String,
+    tags: Option<&[String]>,
+    metadata: Option<&RoadmapMetadata>,
+    connection: &mut PgConnection,
+) -> Result<bool, sqlx::Error> {
+    if let Some(tags) = tags {
+        tag::set_roadmap_tags(roadmap_id, tags, &mut *connection).await?;
+    }
+
+    match metadata {
+        Some(metadata) => Ok(
+            update_roadmap_metadata(roadmap_id, metadata, &mut *connection)
+                .await?
+                .rows_affected()
+                == 1,
+        ),
+        None => Ok(true),
+    }
+}
+
+/// Adds the roadmap along with its tags and metadata, nothing is saved when one of them fails.
+async fn add_roadmap_with_details(
+    roadmap: Roadmaps,
+    tags: &[String],
+    metadata: Option<&RoadmapMetadata>,
+    pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let roadmap_id = roadmap.id.clone();
+    let tags = (!tags.is_empty()).then_some(tags);
+    let mut transaction = pool.begin().await?;
+
+    if add_roadmap(roadmap, &mut *transaction)
+        .await?
+        .rows_affected()
+        != 1
+        || !save_roadmap_details(&roadmap_id, tags, metadata, &mut transaction).await?
+    {
+        return Ok(false);
+    }
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Same as `update_roadmap` but also sets the tags and metadata given, all together or not at
+/// all.
+async fn update_roadmap_with_details(
+    roadmap: Roadmaps,
+    tags: Option<&[String]>,
+    metadata: Option<&RoadmapMetadata>,
+    pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let roadmap_id = roadmap.id.clone();
+    let mut transaction = pool.begin().await?;
+
+    if update_roadmap(roadmap, &mut *transaction)
+        .await?
+        .rows_affected()
+        != 1
+        || !save_roadmap_details(&roadmap_id, tags, metadata, &mut transaction).await?
+    {
+        return Ok(false);
+    }
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Filters of the public roadmap search, missing fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct RoadmapSearch {
+    pub title: String,
+    // normalized, roadmaps have to carry every one of them
+    pub tags: Vec<String>,
+    pub difficulty: Option<Difficulty>,
+    pub content_language: Option<String>,
+    // compared with the author's estimate, or the sum of the areas when there is none
+    pub min_hours: Option<f64>,
+    pub max_hours: Option<f64>,
+}
+
 //TODO: for now using like is fine i guess but try to migrate it to full text search later on
+// the filter is the SEARCH_ROADMAPS function, shared with the tag facets
 async fn find_roadmap(
-    roadmap_title: String,
+    search: &RoadmapSearch,
     limit: i64,
     offset: i64,
     pool: &PgPool,
@@ -124,25 +458,27 @@ async fn find_roadmap(
     let query = sqlx::query_as!(
         Roadmaps,
         r#"
-        SELECT 
-            ID,
-            TITLE,
+        SELECT
+            ID AS "id!",
+            TITLE AS "title!",
             DESCRIPTION,
-            PUBLISHER,
+            PUBLISHER AS "publisher!",
             PUBLISHED,
             CREATED_AT,
             UPDATED_AT
         FROM
-            ROADMAPS
-        WHERE 
-            (TITLE = '' OR TITLE ILIKE $1) AND
-            published = true
+            SEARCH_ROADMAPS($1, $4, $5, $6, $7, $8)
         LIMIT $2
         OFFSET $3
     "#,
-        format!("%{}%", roadmap_title),
+        format!("%{}%", search.title),
         limit,
-        offset
+        offset,
+        &search.tags,
+        search.difficulty.map(|difficulty| difficulty.as_str()),
+        search.content_language,
+        search.min_hours,
+        search.max_hours
     )
     .fetch_all(pool)
     .await;
@@ -150,13 +486,76 @@ async fn find_roadmap(
     query
 }
 
-async fn get_user_roadmaps(user_id: String, pool: &PgPool) -> Result<Vec<Roadmaps>, sqlx::Error> {
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RoadmapSort {
+    Created,
+    Updated,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+pub struct UserRoadmapsQuery {
+    // drafts, private and unlisted roadmaps are only listed for their author
+    pub public_only: bool,
+    pub sort: RoadmapSort,
+    pub order: SortOrder,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Default for UserRoadmapsQuery {
+    fn default() -> Self {
+        UserRoadmapsQuery {
+            public_only: false,
+            sort: RoadmapSort::Created,
+            order: SortOrder::Desc,
+            limit: 20,
+            offset: 0,
+        }
+    }
+}
+
+async fn get_user_roadmaps(
+    user_id: String,
+    user_roadmaps_query: &UserRoadmapsQuery,
+    pool: &PgPool,
+) -> Result<Vec<Roadmaps>, sqlx::Error> {
+    // ORDER BY can't be bound as a parameter, each direction gets its own sort key instead
     let query = sqlx::query_as!(
         Roadmaps,
         r#"
-            SELECT * FROM ROADMAPS WHERE PUBLISHER = $1
+            SELECT
+                ID,
+                TITLE,
+                DESCRIPTION,
+                PUBLISHER,
+                PUBLISHED,
+                CREATED_AT,
+                UPDATED_AT
+            FROM ROADMAPS
+            WHERE
+                PUBLISHER = $1 AND
+                DELETED_AT IS NULL AND
+                (NOT $2 OR (PUBLISHED = TRUE AND VISIBILITY = 'public'))
+            ORDER BY
+                CASE WHEN $4 THEN (CASE WHEN $3 THEN UPDATED_AT ELSE CREATED_AT END) END ASC,
+                CASE WHEN NOT $4 THEN (CASE WHEN $3 THEN UPDATED_AT ELSE CREATED_AT END) END DESC,
+                ID
+            LIMIT $5
+            OFFSET $6
         "#,
-        user_id
+        user_id,
+        user_roadmaps_query.public_only,
+        user_roadmaps_query.sort == RoadmapSort::Updated,
+        user_roadmaps_query.order == SortOrder::Asc,
+        user_roadmaps_query.limit,
+        user_roadmaps_query.offset
     )
     .fetch_all(pool)
     .await;
@@ -182,6 +581,7 @@ async fn get_roadmaps(
             UPDATED_AT
         FROM
             ROADMAPS
+        WHERE DELETED_AT IS NULL
         ORDER BY CREATED_AT
         LIMIT $1
         OFFSET $2
@@ -198,7 +598,7 @@ async fn get_roadmaps(
 
 async fn roadmap_exist(roadmap_id: String, pool: &PgPool) -> Result<bool, sqlx::Error> {
     let query = query!(
-        r#"SELECT EXISTS (SELECT 1 FROM ROADMAPS WHERE ID = $1)"#,
+        r#"SELECT EXISTS (SELECT 1 FROM ROADMAPS WHERE ID = $1 AND DELETED_AT IS NULL)"#,
         roadmap_id
     )
     .fetch_one(pool)
@@ -214,56 +614,135 @@ async fn get_roadmap_by_id(
     roadmap_id: String,
     pool: &PgPool,
 ) -> Result<RoadmapWithAreas, sqlx::Error> {
+    // only forks anyone can see are counted, drafts and private or unlisted ones aren't disclosed
     match query!(
         r#"
         SELECT 
             r.id as roadmap_id,
             r.title as roadmap_title, 
             r.description as roadmap_description,
+            r.publisher as roadmap_publisher,
+            r.published as roadmap_published,
+            r.published_at as roadmap_published_at,
+            r.forked_from as roadmap_forked_from,
+            r.visibility as roadmap_visibility,
+            r.share_token as roadmap_share_token,
+            ARRAY(
+                SELECT T.NAME FROM ROADMAP_TAGS RT
+                JOIN TAGS T ON T.ID = RT.TAG_ID
+                WHERE RT.ROADMAP_ID = R.ID
+                ORDER BY T.NAME
+            ) as "roadmap_tags!",
+            r.difficulty as roadmap_difficulty,
+            r.estimated_hours as roadmap_estimated_hours,
+            r.content_language as roadmap_content_language,
+            r.prerequisite_summary as roadmap_prerequisite_summary,
+            (
+                SELECT COUNT(*) FROM ROADMAPS F
+                WHERE
+                    F.FORKED_FROM = R.ID AND
+                    F.PUBLISHED = TRUE AND
+                    F.VISIBILITY = 'public' AND
+                    F.DELETED_AT IS NULL
+            ) as "fork_count!",
             ra.id as "area_id?",
             ra.title as "area_title?",
             ra.description as "area_description?",
             ra.parent_id as "area_parent_id?",
             ra.x as "x?", 
-            ra.y as "y?"
+            ra.y as "y?",
+            ra.estimated_hours as "area_estimated_hours?",
+            ra.revision as "area_revision?"
         FROM ROADMAPS R 
         LEFT JOIN
-            ROADMAP_AREAS RA ON R.ID = RA.ROADMAP_ID
+            ROADMAP_AREAS RA ON R.ID = RA.ROADMAP_ID AND RA.DELETED_AT IS NULL
         WHERE 
-            R.ID = $1
+            R.ID = $1 AND
+            R.DELETED_AT IS NULL
         "#,
         roadmap_id
     )
     .fetch_all(pool)
     .await
     {
+        Ok(records) if records.is_empty() => Err(sqlx::Error::RowNotFound),
         Ok(records) => {
             let mut roadmap_id = "".to_string();
             let mut roadmap_title = "".to_string();
             let mut roadmap_description = None;
+            let mut publisher = "".to_string();
+            let mut published = false;
+            let mut published_at = None;
+            let mut forked_from = None;
+            let mut fork_count = 0;
+            let mut visibility = RoadmapVisibility::Public;
+            let mut share_token = None;
+            let mut tags = Vec::new();
+            let mut metadata = RoadmapMetadata::default();
             let mut areas: Vec<_> = Vec::new();
             for record in records {
+                roadmap_id = record.roadmap_id.clone();
+                roadmap_title = record.roadmap_title;
+                roadmap_description = record.roadmap_description;
+                publisher = record.roadmap_publisher;
+                published = record.roadmap_published.unwrap_or(false);
+                published_at = record.roadmap_published_at;
+                forked_from = record.roadmap_forked_from;
+                fork_count = record.fork_count;
+                // the check constraint keeps this in sync, falls back to the most restrictive
+                visibility = RoadmapVisibility::parse(&record.roadmap_visibility)
+                    .unwrap_or(RoadmapVisibility::Private);
+                share_token = record.roadmap_share_token;
+                tags = record.roadmap_tags;
+                metadata = RoadmapMetadata {
+                    difficulty: record
+                        .roadmap_difficulty
+                        .as_deref()
+                        .and_then(Difficulty::parse),
+                    estimated_hours: record.roadmap_estimated_hours,
+                    content_language: record.roadmap_content_language,
+                    prerequisite_summary: record.roadmap_prerequisite_summary,
+                };
+
+                // roadmaps without areas still have a single row from the left join
+                let area_id = match record.area_id {
+                    Some(area_id) => area_id,
+                    None => continue,
+                };
                 let area = Areas {
-                    id: record.area_id.unwrap_or("".to_string()),
+                    id: area_id,
                     title: record.area_title.unwrap_or("".to_string()),
                     description: record.area_description,
                     parent_id: record.area_parent_id,
                     roadmap_id: record.roadmap_id.clone(),
                     x: record.x.unwrap_or(0.0),
                     y: record.y.unwrap_or(0.0),
+                    estimated_hours: record.area_estimated_hours,
+                    revision: record.area_revision.unwrap_or(1),
                     created_at: None,
                     updated_at: None,
                 };
                 areas.push(area);
-                roadmap_id = record.roadmap_id.clone();
-                roadmap_title = record.roadmap_title;
-                roadmap_description = record.roadmap_description;
             }
 
             return Ok(RoadmapWithAreas {
                 roadmap_id,
                 roadmap_description,
                 roadmap_title,
+                publisher,
+                published,
+                published_at,
+                forked_from,
+                fork_count,
+                visibility,
+                share_token,
+                tags,
+                metadata,
+                // folded from 0.0 since summing no floats gives -0.0
+                total_estimated_hours: areas
+                    .iter()
+                    .filter_map(|area| area.estimated_hours)
+                    .fold(0.0, |total, hours| total + hours),
                 areas,
             });
         }
@@ -274,13 +753,16 @@ async fn get_roadmap_by_id(
     }
 }
 
-async fn add_areas(area: Areas, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
+async fn add_areas(
+    area: Areas,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
     let query = query!(
         r#"
             INSERT INTO ROADMAP_AREAS
-                (ID, PARENT_ID, TITLE, DESCRIPTION, ROADMAP_ID, X, Y)
+                (ID, PARENT_ID, TITLE, DESCRIPTION, ROADMAP_ID, X, Y, ESTIMATED_HOURS)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7)
+                ($1, $2, $3, $4, $5, $6, $7, $8)
         "#,
         area.id,
         area.parent_id,
@@ -289,57 +771,385 @@ async fn add_areas(area: Areas, pool: &PgPool) -> Result<PgQueryResult, sqlx::Er
         area.roadmap_id,
         area.x,
         area.y,
+        area.estimated_hours,
     )
-    .execute(pool)
+    .execute(executor)
     .await;
 
     query
 }
 
-async fn update_areas(area: Areas, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
-    let query = query!(
+/// The area if it exists in the roadmap and isn't deleted.
+async fn get_area(
+    roadmap_id: &String,
+    area_id: &String,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<Areas>, sqlx::Error> {
+    let record = query!(
+        r#"
+            SELECT
+                ID, PARENT_ID, ROADMAP_ID, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS, REVISION,
+                CREATED_AT, UPDATED_AT
+            FROM ROADMAP_AREAS
+            WHERE
+                ID = $1 AND
+                ROADMAP_ID = $2 AND
+                DELETED_AT IS NULL
+        "#,
+        area_id,
+        roadmap_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record.map(|record| Areas {
+        id: record.id,
+        parent_id: record.parent_id,
+        roadmap_id: record.roadmap_id,
+        title: record.title,
+        description: record.description,
+        x: record.x.unwrap_or(0.0),
+        y: record.y.unwrap_or(0.0),
+        estimated_hours: record.estimated_hours,
+        revision: record.revision,
+        created_at: record.created_at,
+        updated_at: record.updated_at,
+    }))
+}
+
+/// Applies `fields` of `area` on top of the stored area, as a change based on `base_revision`.
+/// A field someone else changed after `base_revision` is a conflict unless it already has the
+/// same value, fields that weren't changed by anyone else are merged. `RowNotFound` when the
+/// area doesn't exist in the roadmap or was deleted. Given a transaction, the update is made in a
+/// savepoint of it.
+async fn update_area(
+    area: &Areas,
+    base_revision: i64,
+    fields: &[AreaField],
+    connection: impl Acquire<'_, Database = Postgres>,
+) -> Result<AreaUpdate, sqlx::Error> {
+    let mut transaction = connection.begin().await?;
+
+    let current = query!(
+        r#"
+            SELECT
+                ID, PARENT_ID, ROADMAP_ID, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS, REVISION,
+                PARENT_REVISION, TITLE_REVISION, DESCRIPTION_REVISION, POSITION_REVISION,
+                ESTIMATED_HOURS_REVISION, CREATED_AT, UPDATED_AT
+            FROM ROADMAP_AREAS
+            WHERE
+                ID = $1 AND
+                ROADMAP_ID = $2 AND
+                DELETED_AT IS NULL
+            FOR UPDATE
+        "#,
+        area.id,
+        area.roadmap_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let field_revision = |field: &AreaField| match field {
+        AreaField::ParentId => current.parent_revision,
+        AreaField::Title => current.title_revision,
+        AreaField::Description => current.description_revision,
+        AreaField::Position => current.position_revision,
+        AreaField::EstimatedHours => current.estimated_hours_revision,
+    };
+    let is_changed = |field: &AreaField| match field {
+        AreaField::ParentId => area.parent_id != current.parent_id,
+        AreaField::Title => area.title != current.title,
+        AreaField::Description => area.description != current.description,
+        AreaField::Position => Some(area.x) != current.x || Some(area.y) != current.y,
+        AreaField::EstimatedHours => area.estimated_hours != current.estimated_hours,
+    };
+
+    let changed: Vec<AreaField> = AreaField::ALL
+        .into_iter()
+        .filter(|field| fields.contains(field) && is_changed(field))
+        .collect();
+    let conflicts: Vec<AreaField> = changed
+        .iter()
+        .filter(|field| field_revision(field) > base_revision)
+        .copied()
+        .collect();
+
+    let mut merged = Areas {
+        id: current.id,
+        parent_id: current.parent_id,
+        roadmap_id: current.roadmap_id,
+        title: current.title,
+        description: current.description,
+        x: current.x.unwrap_or(0.0),
+        y: current.y.unwrap_or(0.0),
+        estimated_hours: current.estimated_hours,
+        revision: current.revision,
+        created_at: current.created_at,
+        updated_at: current.updated_at,
+    };
+
+    if !conflicts.is_empty() {
+        return Ok(AreaUpdate::Conflict {
+            fields: conflicts,
+            current: merged,
+        });
+    }
+    if changed.is_empty() {
+        return Ok(AreaUpdate::Applied {
+            before: merged.clone(),
+            area: merged,
+        });
+    }
+    let before = merged.clone();
+
+    for field in &changed {
+        match field {
+            AreaField::ParentId => merged.parent_id = area.parent_id.clone(),
+            AreaField::Title => merged.title = area.title.clone(),
+            AreaField::Description => merged.description = area.description.clone(),
+            AreaField::Position => (merged.x, merged.y) = (area.x, area.y),
+            AreaField::EstimatedHours => merged.estimated_hours = area.estimated_hours,
+        }
+    }
+    merged.revision += 1;
+
+    let record = query!(
         r#"
             UPDATE ROADMAP_AREAS
-            SET 
-                parent_id = $1,
-                title = $2,
-                description = $3,
+            SET
+                PARENT_ID = $2,
+                TITLE = $3,
+                DESCRIPTION = $4,
                 X = $5,
-                Y = $6
-                WHERE 
-                    id = $4
+                Y = $6,
+                ESTIMATED_HOURS = $7,
+                REVISION = $8,
+                PARENT_REVISION = CASE WHEN $9 THEN $8 ELSE PARENT_REVISION END,
+                TITLE_REVISION = CASE WHEN $10 THEN $8 ELSE TITLE_REVISION END,
+                DESCRIPTION_REVISION = CASE WHEN $11 THEN $8 ELSE DESCRIPTION_REVISION END,
+                POSITION_REVISION = CASE WHEN $12 THEN $8 ELSE POSITION_REVISION END,
+                ESTIMATED_HOURS_REVISION = CASE WHEN $13 THEN $8 ELSE ESTIMATED_HOURS_REVISION END,
+                UPDATED_AT = NOW()
+            WHERE ID = $1
+            RETURNING UPDATED_AT
         "#,
-        area.parent_id,
-        area.title,
-        area.description,
-        area.id,
-        area.x,
-        area.y
+        merged.id,
+        merged.parent_id,
+        merged.title,
+        merged.description,
+        merged.x,
+        merged.y,
+        merged.estimated_hours,
+        merged.revision,
+        changed.contains(&AreaField::ParentId),
+        changed.contains(&AreaField::Title),
+        changed.contains(&AreaField::Description),
+        changed.contains(&AreaField::Position),
+        changed.contains(&AreaField::EstimatedHours)
     )
-    .execute(pool)
-    .await;
+    .fetch_one(&mut *transaction)
+    .await?;
+    merged.updated_at = record.updated_at;
 
-    query
+    transaction.commit().await?;
+
+    Ok(AreaUpdate::Applied {
+        before,
+        area: merged,
+    })
 }
 
-//TODO: probably use soft delete
-async fn delete_area(area_id: String, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
+/// An area moved by `move_areas`, with its new position and the position and revision it had
+/// before.
+#[derive(Debug, Clone)]
+pub struct MovedArea {
+    pub id: String,
+    pub x: f64,
+    pub y: f64,
+    pub from_x: f64,
+    pub from_y: f64,
+    pub from_revision: i64,
+}
+
+/// Moves the areas in a single statement without checking for conflicts, dragging an area is
+/// continuous so the last position wins. Areas that don't exist in the roadmap or were deleted
+/// are skipped, returns the areas moved.
+async fn move_areas(
+    roadmap_id: &String,
+    area_ids: &[String],
+    xs: &[f64],
+    ys: &[f64],
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<MovedArea>, sqlx::Error> {
+    // joined again as P since RETURNING only sees the new values
+    sqlx::query_as!(
+        MovedArea,
+        r#"
+            UPDATE ROADMAP_AREAS A
+            SET
+                X = M.X,
+                Y = M.Y,
+                REVISION = A.REVISION + 1,
+                POSITION_REVISION = A.REVISION + 1,
+                UPDATED_AT = NOW()
+            FROM
+                UNNEST($2::VARCHAR[], $3::DOUBLE PRECISION[], $4::DOUBLE PRECISION[])
+                    AS M(ID, X, Y),
+                ROADMAP_AREAS P
+            WHERE
+                A.ID = M.ID AND
+                P.ID = A.ID AND
+                A.ROADMAP_ID = $1 AND
+                A.DELETED_AT IS NULL
+            RETURNING
+                A.ID, COALESCE(A.X, 0) AS "x!", COALESCE(A.Y, 0) AS "y!",
+                COALESCE(P.X, 0) AS "from_x!", COALESCE(P.Y, 0) AS "from_y!",
+                P.REVISION AS from_revision
+        "#,
+        roadmap_id,
+        area_ids,
+        xs,
+        ys
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Whether `parent_id` can be the parent of `area_id`: it has to be a live area of the same
+/// roadmap and not the area itself or one of its descendants. `area_id` is `None` for areas
+/// that don't exist yet.
+async fn is_valid_parent(
+    roadmap_id: &String,
+    area_id: Option<&String>,
+    parent_id: &String,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, sqlx::Error> {
+    let record = query!(
+        r#"
+            WITH RECURSIVE DESCENDANTS AS (
+                SELECT ID FROM ROADMAP_AREAS WHERE ID = $2 AND ROADMAP_ID = $1
+                UNION
+                SELECT A.ID FROM ROADMAP_AREAS A
+                JOIN DESCENDANTS D ON A.PARENT_ID = D.ID
+            )
+            SELECT
+                EXISTS (
+                    SELECT 1 FROM ROADMAP_AREAS
+                    WHERE ID = $3 AND ROADMAP_ID = $1 AND DELETED_AT IS NULL
+                ) AND
+                NOT EXISTS (SELECT 1 FROM DESCENDANTS WHERE ID = $3) AS "is_valid!"
+        "#,
+        roadmap_id,
+        area_id,
+        parent_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(record.is_valid)
+}
+
+/// Sets the parent of the area, `None` makes it a root area. The parent has to be checked with
+/// `is_valid_parent` first. Like moves, links don't check for conflicts. Returns the new
+/// revision of the area, `None` when it doesn't exist in the roadmap or was deleted.
+async fn link_areas(
+    roadmap_id: &String,
+    child_id: &String,
+    parent_id: Option<&String>,
+    executor: impl PgExecutor<'_>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let record = query!(
+        r#"
+            UPDATE ROADMAP_AREAS
+            SET
+                PARENT_ID = $3,
+                REVISION = REVISION + 1,
+                PARENT_REVISION = REVISION + 1,
+                UPDATED_AT = NOW()
+            WHERE
+                ID = $2 AND
+                ROADMAP_ID = $1 AND
+                DELETED_AT IS NULL
+            RETURNING REVISION
+        "#,
+        roadmap_id,
+        child_id,
+        parent_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(record.map(|record| record.revision))
+}
+
+/// Moves the area and all of its descendants to the trash, returns the number of areas deleted.
+async fn delete_area(
+    roadmap_id: String,
+    area_id: String,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    // scoped to the roadmap so the ownership check on the roadmap covers its areas
     let query = query!(
         r#"
-        DELETE FROM ROADMAP_AREAS WHERE ID = $1
+        WITH RECURSIVE SUBTREE AS (
+            SELECT ID FROM ROADMAP_AREAS
+            WHERE ID = $1 AND ROADMAP_ID = $2 AND DELETED_AT IS NULL
+            UNION
+            SELECT RA.ID FROM ROADMAP_AREAS RA
+            JOIN SUBTREE S ON RA.PARENT_ID = S.ID
+            WHERE RA.DELETED_AT IS NULL
+        )
+        UPDATE ROADMAP_AREAS
+        SET DELETED_AT = NOW()
+        WHERE ID IN (SELECT ID FROM SUBTREE)
     "#,
-        area_id
+        area_id,
+        roadmap_id
     )
-    .execute(pool)
+    .execute(executor)
     .await;
 
     query
 }
 
+/// Same as `delete_area` but also logs the delete as a change of `author`, both or neither.
+/// `false` when the area doesn't exist in this roadmap or was already deleted.
+async fn delete_area_with_history(
+    roadmap_id: &String,
+    area_id: &String,
+    author: &str,
+    pool: &PgPool,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let Some(before) = get_area(roadmap_id, area_id, &mut *transaction).await? else {
+        return Ok(false);
+    };
+    if delete_area(roadmap_id.clone(), area_id.clone(), &mut *transaction)
+        .await?
+        .rows_affected()
+        == 0
+    {
+        return Ok(false);
+    }
+    let change = NewAreaChange {
+        area_id: area_id.clone(),
+        kind: ChangeKind::Delete,
+        source: ChangeSource::Edit,
+        target_id: None,
+        before: Some(before.into()),
+        after: None,
+    };
+    add_area_change(roadmap_id, author, &change, &mut *transaction).await?;
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
 async fn delete_roadmap(roadmap_id: String, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
     let query = query!(
         r#"
-            DELETE FROM ROADMAPS WHERE ID = $1
+            UPDATE ROADMAPS SET DELETED_AT = NOW() WHERE ID = $1 AND DELETED_AT IS NULL
         "#,
         roadmap_id
     )
@@ -349,6 +1159,735 @@ async fn delete_roadmap(roadmap_id: String, pool: &PgPool) -> Result<PgQueryResu
     query
 }
 
+/// Reasons the roadmap can't be published yet, empty when it's ready.
+async fn validate_roadmap_for_publish(
+    roadmap_id: String,
+    pool: &PgPool,
+) -> Result<Vec<String>, sqlx::Error> {
+    let record = query!(
+        r#"
+            SELECT
+                R.TITLE,
+                (
+                    SELECT COUNT(*) FROM ROADMAP_AREAS RA
+                    WHERE RA.ROADMAP_ID = R.ID AND RA.DELETED_AT IS NULL
+                ) AS "total_areas!",
+                (
+                    SELECT COUNT(*) FROM ROADMAP_AREAS RA
+                    LEFT JOIN ROADMAP_AREAS P ON
+                        P.ID = RA.PARENT_ID AND
+                        P.ROADMAP_ID = RA.ROADMAP_ID AND
+                        P.DELETED_AT IS NULL
+                    WHERE
+                        RA.ROADMAP_ID = R.ID AND
+                        RA.DELETED_AT IS NULL AND
+                        RA.PARENT_ID IS NOT NULL AND
+                        P.ID IS NULL
+                ) AS "dangling_areas!"
+            FROM ROADMAPS R
+            WHERE R.ID = $1 AND R.DELETED_AT IS NULL
+        "#,
+        roadmap_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let mut problems = Vec::new();
+    if record.title.trim().is_empty() {
+        problems.push(String::from("Roadmap title can't be empty"));
+    }
+    if record.total_areas == 0 {
+        problems.push(String::from("Roadmap needs at least one area"));
+    }
+    if record.dangling_areas > 0 {
+        problems.push(format!(
+            "{} areas have a parent that is not part of the roadmap",
+            record.dangling_areas
+        ));
+    }
+
+    Ok(problems)
+}
+
+async fn publish_roadmap(
+    roadmap_id: String,
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    // publishing an already published roadmap keeps its original PUBLISHED_AT
+    query!(
+        r#"
+            UPDATE ROADMAPS
+            SET
+                PUBLISHED = TRUE,
+                PUBLISHED_AT = CASE WHEN PUBLISHED THEN COALESCE(PUBLISHED_AT, NOW()) ELSE NOW() END,
+                UPDATED_AT = NOW()
+            WHERE
+                ID = $1 AND
+                PUBLISHER = $2 AND
+                DELETED_AT IS NULL
+        "#,
+        roadmap_id,
+        publisher
+    )
+    .execute(pool)
+    .await
+}
+
+async fn unpublish_roadmap(
+    roadmap_id: String,
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+            UPDATE ROADMAPS
+            SET PUBLISHED = FALSE, PUBLISHED_AT = NULL, UPDATED_AT = NOW()
+            WHERE
+                ID = $1 AND
+                PUBLISHER = $2 AND
+                DELETED_AT IS NULL
+        "#,
+        roadmap_id,
+        publisher
+    )
+    .execute(pool)
+    .await
+}
+
+/// Copies a roadmap with its areas and tags into `publisher`'s account as a draft, areas get
+/// new ids and keep their hierarchy. Returns the id of the fork.
+async fn fork_roadmap(
+    roadmap_id: String,
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<String, sqlx::Error> {
+    let fork_id = generate_random_str(DEFAULT_ID_LENGTH);
+    let mut transaction = pool.begin().await?;
+
+    let forked = query!(
+        r#"
+            INSERT INTO ROADMAPS
+                (
+                    ID, TITLE, DESCRIPTION, PUBLISHER, PUBLISHED, FORKED_FROM, DIFFICULTY,
+                    ESTIMATED_HOURS, CONTENT_LANGUAGE, PREREQUISITE_SUMMARY
+                )
+            SELECT
+                $1, TITLE, DESCRIPTION, $2, FALSE, ID, DIFFICULTY, ESTIMATED_HOURS,
+                CONTENT_LANGUAGE, PREREQUISITE_SUMMARY
+            FROM ROADMAPS
+            WHERE ID = $3 AND DELETED_AT IS NULL
+        "#,
+        fork_id,
+        publisher,
+        roadmap_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if forked.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    query!(
+        r#"
+            INSERT INTO ROADMAP_TAGS (ROADMAP_ID, TAG_ID)
+            SELECT $1, TAG_ID FROM ROADMAP_TAGS WHERE ROADMAP_ID = $2
+        "#,
+        fork_id,
+        roadmap_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let areas = query!(
+        r#"
+            SELECT ID, PARENT_ID, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS
+            FROM ROADMAP_AREAS
+            WHERE ROADMAP_ID = $1 AND DELETED_AT IS NULL
+        "#,
+        roadmap_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    let area_ids: HashMap<&String, String> = areas
+        .iter()
+        .map(|area| (&area.id, generate_random_str(DEFAULT_ID_LENGTH)))
+        .collect();
+
+    // a single statement since foreign keys on PARENT_ID are checked once it completes
+    query!(
+        r#"
+            INSERT INTO ROADMAP_AREAS
+                (ID, PARENT_ID, ROADMAP_ID, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS)
+            SELECT ID, PARENT_ID, $3, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS
+            FROM UNNEST(
+                $1::VARCHAR[],
+                $2::VARCHAR[],
+                $4::VARCHAR[],
+                $5::TEXT[],
+                $6::DOUBLE PRECISION[],
+                $7::DOUBLE PRECISION[],
+                $8::DOUBLE PRECISION[]
+            ) AS A(ID, PARENT_ID, TITLE, DESCRIPTION, X, Y, ESTIMATED_HOURS)
+        "#,
+        &areas
+            .iter()
+            .map(|area| area_ids[&area.id].clone())
+            .collect::<Vec<_>>(),
+        // parents outside of the copied areas (e.g. deleted ones) are dropped
+        &areas
+            .iter()
+            .map(|area| {
+                area.parent_id
+                    .as_ref()
+                    .and_then(|parent_id| area_ids.get(parent_id).cloned())
+            })
+            .collect::<Vec<_>>() as &[Option<String>],
+        fork_id,
+        &areas
+            .iter()
+            .map(|area| area.title.clone())
+            .collect::<Vec<_>>(),
+        &areas
+            .iter()
+            .map(|area| area.description.clone())
+            .collect::<Vec<_>>() as &[Option<String>],
+        &areas
+            .iter()
+            .map(|area| area.x.unwrap_or(0.0))
+            .collect::<Vec<_>>(),
+        &areas
+            .iter()
+            .map(|area| area.y.unwrap_or(0.0))
+            .collect::<Vec<_>>(),
+        &areas
+            .iter()
+            .map(|area| area.estimated_hours)
+            .collect::<Vec<_>>() as &[Option<f64>]
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(fork_id)
+}
+
+async fn update_roadmap_metadata(
+    roadmap_id: &String,
+    metadata: &RoadmapMetadata,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+            UPDATE ROADMAPS
+            SET
+                DIFFICULTY = $2,
+                ESTIMATED_HOURS = $3,
+                CONTENT_LANGUAGE = $4,
+                PREREQUISITE_SUMMARY = $5,
+                UPDATED_AT = NOW()
+            WHERE ID = $1 AND DELETED_AT IS NULL
+        "#,
+        roadmap_id,
+        metadata.difficulty.map(|difficulty| difficulty.as_str()),
+        metadata.estimated_hours,
+        metadata.content_language,
+        metadata.prerequisite_summary
+    )
+    .execute(executor)
+    .await
+}
+
+/// Changes who can see the roadmap, unlisted roadmaps get a share token if they don't have
+/// one yet. Returns the share token, `RowNotFound` when the roadmap doesn't exist.
+async fn set_roadmap_visibility(
+    roadmap_id: String,
+    visibility: RoadmapVisibility,
+    pool: &PgPool,
+) -> Result<Option<String>, sqlx::Error> {
+    let record = query!(
+        r#"
+            UPDATE ROADMAPS
+            SET
+                VISIBILITY = $2,
+                SHARE_TOKEN = CASE
+                    WHEN $2::VARCHAR = 'unlisted' THEN COALESCE(SHARE_TOKEN, $3)
+                    ELSE SHARE_TOKEN
+                END,
+                UPDATED_AT = NOW()
+            WHERE ID = $1 AND DELETED_AT IS NULL
+            RETURNING SHARE_TOKEN
+        "#,
+        roadmap_id,
+        visibility.as_str(),
+        generate_random_str(SHARE_TOKEN_LENGTH)
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(record.share_token)
+}
+
+/// Replaces the share token so previously shared links stop working. Returns the new token,
+/// `RowNotFound` when the roadmap doesn't exist.
+async fn rotate_share_token(roadmap_id: String, pool: &PgPool) -> Result<String, sqlx::Error> {
+    let share_token = generate_random_str(SHARE_TOKEN_LENGTH);
+    let rotated = query!(
+        r#"
+            UPDATE ROADMAPS
+            SET SHARE_TOKEN = $2, UPDATED_AT = NOW()
+            WHERE ID = $1 AND DELETED_AT IS NULL
+        "#,
+        roadmap_id,
+        share_token
+    )
+    .execute(pool)
+    .await?;
+
+    if rotated.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    Ok(share_token)
+}
+
+/// Access a user has on a roadmap, ordered from the least to the most privileged. The owner is
+/// the publisher, editors and viewers are accepted collaborators.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RoadmapRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl RoadmapRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoadmapRole::Viewer => "viewer",
+            RoadmapRole::Editor => "editor",
+            RoadmapRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RoadmapRole> {
+        [RoadmapRole::Viewer, RoadmapRole::Editor, RoadmapRole::Owner]
+            .into_iter()
+            .find(|role| role.as_str() == value)
+    }
+}
+
+#[derive(Debug)]
+pub struct Collaborator {
+    roadmap_id: String,
+    username: String,
+    role: String,
+    invited_by: Option<String>,
+    accepted_at: Option<NaiveDateTime>,
+    created_at: Option<NaiveDateTime>,
+}
+
+/// Role of `username` on the roadmap, `None` when they have no access (including pending
+/// invitations), `RowNotFound` when the roadmap doesn't exist.
+async fn get_roadmap_role(
+    roadmap_id: String,
+    username: &String,
+    pool: &PgPool,
+) -> Result<Option<RoadmapRole>, sqlx::Error> {
+    let record = query!(
+        r#"
+            SELECT R.PUBLISHER, C.ROLE AS "role?"
+            FROM ROADMAPS R
+            LEFT JOIN ROADMAP_COLLABORATORS C ON
+                C.ROADMAP_ID = R.ID AND
+                C.USERNAME = $2 AND
+                C.ACCEPTED_AT IS NOT NULL
+            WHERE R.ID = $1 AND R.DELETED_AT IS NULL
+        "#,
+        roadmap_id,
+        username
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+
+    if &record.publisher == username {
+        return Ok(Some(RoadmapRole::Owner));
+    }
+
+    Ok(record.role.as_deref().and_then(RoadmapRole::parse))
+}
+
+/// Invites `username` to collaborate, inviting an existing collaborator again changes their
+/// role and keeps them accepted.
+async fn invite_collaborator(
+    roadmap_id: String,
+    username: &String,
+    role: RoadmapRole,
+    invited_by: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+            INSERT INTO ROADMAP_COLLABORATORS
+                (ROADMAP_ID, USERNAME, ROLE, INVITED_BY)
+            VALUES
+                ($1, $2, $3, $4)
+            ON CONFLICT (ROADMAP_ID, USERNAME) DO UPDATE
+            SET ROLE = EXCLUDED.ROLE, UPDATED_AT = NOW()
+        "#,
+        roadmap_id,
+        username,
+        role.as_str(),
+        invited_by
+    )
+    .execute(pool)
+    .await
+}
+
+async fn accept_collaboration(
+    roadmap_id: String,
+    username: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+            UPDATE ROADMAP_COLLABORATORS
+            SET ACCEPTED_AT = NOW(), UPDATED_AT = NOW()
+            WHERE
+                ROADMAP_ID = $1 AND
+                USERNAME = $2 AND
+                ACCEPTED_AT IS NULL
+        "#,
+        roadmap_id,
+        username
+    )
+    .execute(pool)
+    .await
+}
+
+async fn remove_collaborator(
+    roadmap_id: String,
+    username: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"DELETE FROM ROADMAP_COLLABORATORS WHERE ROADMAP_ID = $1 AND USERNAME = $2"#,
+        roadmap_id,
+        username
+    )
+    .execute(pool)
+    .await
+}
+
+async fn get_collaborators(
+    roadmap_id: String,
+    pool: &PgPool,
+) -> Result<Vec<Collaborator>, sqlx::Error> {
+    sqlx::query_as!(
+        Collaborator,
+        r#"
+            SELECT ROADMAP_ID, USERNAME, ROLE, INVITED_BY, ACCEPTED_AT, CREATED_AT
+            FROM ROADMAP_COLLABORATORS
+            WHERE ROADMAP_ID = $1
+            ORDER BY CREATED_AT
+        "#,
+        roadmap_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn get_pending_invitations(
+    username: &String,
+    pool: &PgPool,
+) -> Result<Vec<Collaborator>, sqlx::Error> {
+    sqlx::query_as!(
+        Collaborator,
+        r#"
+            SELECT C.ROADMAP_ID, C.USERNAME, C.ROLE, C.INVITED_BY, C.ACCEPTED_AT, C.CREATED_AT
+            FROM ROADMAP_COLLABORATORS C
+            JOIN ROADMAPS R ON R.ID = C.ROADMAP_ID
+            WHERE
+                C.USERNAME = $1 AND
+                C.ACCEPTED_AT IS NULL AND
+                R.DELETED_AT IS NULL
+            ORDER BY C.CREATED_AT DESC
+        "#,
+        username
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Hands the roadmap over to `new_owner`, who has to be an accepted collaborator. The previous
+/// owner stays on as an editor. `RowNotFound` when either side doesn't match.
+async fn transfer_roadmap_ownership(
+    roadmap_id: String,
+    owner: &String,
+    new_owner: &String,
+    pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let removed = query!(
+        r#"
+            DELETE FROM ROADMAP_COLLABORATORS
+            WHERE
+                ROADMAP_ID = $1 AND
+                USERNAME = $2 AND
+                ACCEPTED_AT IS NOT NULL
+        "#,
+        roadmap_id,
+        new_owner
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if removed.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    let transferred = query!(
+        r#"
+            UPDATE ROADMAPS
+            SET PUBLISHER = $1, UPDATED_AT = NOW()
+            WHERE
+                ID = $2 AND
+                PUBLISHER = $3 AND
+                DELETED_AT IS NULL
+        "#,
+        new_owner,
+        roadmap_id,
+        owner
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if transferred.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    query!(
+        r#"
+            INSERT INTO ROADMAP_COLLABORATORS
+                (ROADMAP_ID, USERNAME, ROLE, INVITED_BY, ACCEPTED_AT)
+            VALUES
+                ($1, $2, $3, $4, NOW())
+        "#,
+        roadmap_id,
+        owner,
+        RoadmapRole::Editor.as_str(),
+        new_owner
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+#[derive(Debug)]
+pub struct TrashedRoadmap {
+    id: String,
+    title: String,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug)]
+pub struct TrashedArea {
+    id: String,
+    roadmap_id: String,
+    parent_id: Option<String>,
+    title: String,
+    deleted_at: Option<NaiveDateTime>,
+}
+
+async fn get_trashed_roadmaps(
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<Vec<TrashedRoadmap>, sqlx::Error> {
+    sqlx::query_as!(
+        TrashedRoadmap,
+        r#"
+            SELECT ID, TITLE, DELETED_AT
+            FROM ROADMAPS
+            WHERE
+                PUBLISHER = $1 AND
+                DELETED_AT IS NOT NULL
+            ORDER BY DELETED_AT DESC
+        "#,
+        publisher
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Areas deleted from roadmaps that are not themselves in the trash, only the root of each
+/// deleted subtree is listed since restoring it brings back the rest.
+async fn get_trashed_areas(
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<Vec<TrashedArea>, sqlx::Error> {
+    sqlx::query_as!(
+        TrashedArea,
+        r#"
+            SELECT RA.ID, RA.ROADMAP_ID, RA.PARENT_ID, RA.TITLE, RA.DELETED_AT
+            FROM ROADMAP_AREAS RA
+            JOIN ROADMAPS R ON R.ID = RA.ROADMAP_ID
+            LEFT JOIN ROADMAP_AREAS P ON P.ID = RA.PARENT_ID
+            WHERE
+                R.PUBLISHER = $1 AND
+                R.DELETED_AT IS NULL AND
+                RA.DELETED_AT IS NOT NULL AND
+                (P.ID IS NULL OR P.DELETED_AT IS DISTINCT FROM RA.DELETED_AT)
+            ORDER BY RA.DELETED_AT DESC
+        "#,
+        publisher
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn restore_roadmap(
+    roadmap_id: String,
+    publisher: &String,
+    pool: &PgPool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+            UPDATE ROADMAPS
+            SET DELETED_AT = NULL, UPDATED_AT = NOW()
+            WHERE
+                ID = $1 AND
+                PUBLISHER = $2 AND
+                DELETED_AT IS NOT NULL
+        "#,
+        roadmap_id,
+        publisher
+    )
+    .execute(pool)
+    .await
+}
+
+/// Restores the area and the descendants that were deleted along with it. Nothing is restored
+/// when the area isn't in the trash or its parent is still deleted.
+async fn restore_area(
+    roadmap_id: String,
+    area_id: String,
+    executor: impl PgExecutor<'_>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    query!(
+        r#"
+        WITH RECURSIVE ROOT AS (
+            SELECT RA.ID, RA.DELETED_AT FROM ROADMAP_AREAS RA
+            LEFT JOIN ROADMAP_AREAS P ON P.ID = RA.PARENT_ID
+            WHERE
+                RA.ID = $1 AND
+                RA.ROADMAP_ID = $2 AND
+                RA.DELETED_AT IS NOT NULL AND
+                P.DELETED_AT IS NULL
+        ), SUBTREE AS (
+            SELECT ID FROM ROOT
+            UNION
+            SELECT RA.ID FROM ROADMAP_AREAS RA
+            JOIN SUBTREE S ON RA.PARENT_ID = S.ID
+            WHERE RA.DELETED_AT = (SELECT DELETED_AT FROM ROOT)
+        )
+        UPDATE ROADMAP_AREAS
+        SET DELETED_AT = NULL, UPDATED_AT = NOW()
+        WHERE ID IN (SELECT ID FROM SUBTREE)
+    "#,
+        area_id,
+        roadmap_id
+    )
+    .execute(executor)
+    .await
+}
+
+/// Same as `restore_area` but also logs the restore as a change of `author`, both or neither.
+/// Returns the number of areas restored.
+async fn restore_area_with_history(
+    roadmap_id: &String,
+    area_id: &String,
+    author: &str,
+    pool: &PgPool,
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    let restored = restore_area(roadmap_id.clone(), area_id.clone(), &mut *transaction)
+        .await?
+        .rows_affected();
+    if restored == 0 {
+        return Ok(0);
+    }
+    let after = get_area(roadmap_id, area_id, &mut *transaction).await?;
+    let change = NewAreaChange {
+        area_id: area_id.clone(),
+        kind: ChangeKind::Restore,
+        source: ChangeSource::Edit,
+        target_id: None,
+        before: None,
+        after: after.map(AreaSnapshot::from),
+    };
+    add_area_change(roadmap_id, author, &change, &mut *transaction).await?;
+
+    transaction.commit().await?;
+    Ok(restored)
+}
+
+/// Permanently removes roadmaps and areas that have been in the trash for longer than
+/// `retention_days`, returns the number of roadmaps and areas purged.
+pub async fn purge_trash(retention_days: i32, pool: &PgPool) -> Result<(u64, u64), sqlx::Error> {
+    // compared with NOW() since DELETED_AT is set by postgres as well
+    let mut transaction = pool.begin().await?;
+
+    let roadmaps = query!(
+        "DELETE FROM ROADMAPS WHERE DELETED_AT < NOW() - MAKE_INTERVAL(DAYS => $1)",
+        retention_days
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // descendants are deleted at the same time, PARENT_ID cascades to them anyway
+    let areas = query!(
+        "DELETE FROM ROADMAP_AREAS WHERE DELETED_AT < NOW() - MAKE_INTERVAL(DAYS => $1)",
+        retention_days
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok((roadmaps.rows_affected(), areas.rows_affected()))
+}
+
+/// Runs forever, purging the trash every `purge_interval_secs`.
+pub async fn purge_trash_periodically(pool: PgPool, trash_config: TrashConfig) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        trash_config.purge_interval_secs.max(1).into(),
+    ));
+
+    loop {
+        interval.tick().await;
+
+        let retention_days = i32::try_from(trash_config.retention_days).unwrap_or(i32::MAX);
+        match purge_trash(retention_days, &pool).await {
+            Ok((0, 0)) => {}
+            Ok((roadmaps, areas)) => info!(
+                "[roadmap][purge_trash] purged {} roadmaps and {} areas",
+                roadmaps, areas
+            ),
+            Err(err) => error!(
+                "[roadmap][purge_trash] error on purging trash with cause {}",
+                err
+            ),
+        }
+    }
+}
+
 async fn like_roadmap(roadmap_id: String, pool: &PgPool) -> Result<PgQueryResult, sqlx::Error> {
     todo!()
 }
@@ -0,0 +1,332 @@
+//! Messages exchanged on the area websocket, `GET /api/v1/roadmaps/:roadmap_id/areas`.
+//!
+//! Every frame is an object tagged with its `type`, shown here as JSON, the encoding of the
+//! frames is negotiated when connecting, see [`super::codec`]. Clients can attach a `request_id` to
+//! any request, it is echoed back on the `ack`, `error` or `pong` answering it so responses can
+//! be matched to requests even when several are in flight:
+//!
+//! ```json
+//! {"type": "move_area", "request_id": "42", "area_id": "a1b2c3", "x": 10.0, "y": 20.0}
+//! {"type": "ack", "request_id": "42", "area_id": "a1b2c3"}
+//! ```
+//!
+//! Right after the `welcome` the server sends a `snapshot` of the roadmap. Changes committed by
+//! one editor are then broadcast to everyone else connected to the roadmap as `area_upserted`,
+//! `area_deleted`, `area_moved`, `areas_linked` and `area_restored` events, the editor making
+//! the change only gets the `ack`.
+//!
+//! The snapshot and every change carry a `sequence`, increasing by one with each change of the
+//! roadmap, the `ack` carries the sequence given to the change it acknowledges. Changes with a
+//! sequence above the snapshot's can already be in it, the area revisions tell. A client that
+//! lost its connection reconnects with the `stream_id` of its `welcome` and the last sequence it
+//! got as the `stream_id` and `resume_from` query parameters, it is then sent the changes it
+//! missed instead of a snapshot when they are all still kept, see `resumed` on the `welcome`.
+//!
+//! Everyone connected is told when an editor joins or leaves, and `cursor` messages are relayed
+//! to the other editors as they come. Cursors are never stored nor acknowledged, the latest one
+//! of each editor is all that matters.
+//!
+//! Every area has a revision, bumped on each change. Updates carry the revision they are based
+//! on and the fields they change, when someone else changed one of those fields in the meantime
+//! the update is rejected with a `conflict` holding the area as it is now. Changes to other
+//! fields are merged. Links are applied as they come, the last one wins.
+//!
+//! Requests holding values that can't be stored, a blank or too long title, an estimate out of
+//! range or a position that isn't a finite number, are rejected with `invalid_message`, cursors
+//! included.
+//!
+//! Moves are acknowledged right away but only written to the database once per tick,
+//! `WEBSOCKET_FLUSH_INTERVAL_MS`, keeping the last position of each area, and broadcast once
+//! written. Their `ack` has neither a revision nor a sequence, and moves of areas deleted in
+//! the meantime are dropped.
+//!
+//! `undo` and `redo` undo the latest change of the user on the roadmap, or redo their latest
+//! undo, whichever connection it was made from. They are acknowledged and broadcast like the
+//! change they apply. An update can't be undone once one of its fields was changed again, the
+//! `conflict` holds the area as it is now. A change of an area deleted since is answered with
+//! `not_found`. Either way the change is skipped, the next undo moves past it to the change
+//! before. Redos are skipped the same way. The history of the roadmap is at
+//! `GET /api/v1/roadmaps/:roadmap_id/history`.
+//!
+//! The server pings every connection and closes it when the client sent nothing for too long,
+//! when it reached its maximum lifetime, when the user can't edit the roadmap anymore or when
+//! the client sends too much, with the reason on the close frame. Standard close codes are used
+//! where they fit, `1001` on shutdown, `1003` for frames of the wrong type for the subprotocol,
+//! `1008` for too many messages, `1009` for messages too large and `1011` for server errors,
+//! the `CLOSE_*` codes otherwise. Clients should reconnect, resuming, unless the code is
+//! `CLOSE_UNAUTHORIZED`, `CLOSE_FORBIDDEN` or `CLOSE_NOT_FOUND`.
+//!
+//! The protocol version is picked with the `protocol_version` query parameter when connecting,
+//! the server confirms it on the `welcome` event sent right after the upgrade.
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::{validate_area, validate_position, AreaField, Areas};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const SUPPORTED_PROTOCOL_VERSIONS: [u32; 1] = [PROTOCOL_VERSION];
+
+/// Version used for the connection, the latest when the client doesn't ask for one. `None`
+/// when the requested version isn't supported.
+pub fn negotiate_protocol_version(requested: Option<u32>) -> Option<u32> {
+    match requested {
+        None => Some(PROTOCOL_VERSION),
+        Some(version) => SUPPORTED_PROTOCOL_VERSIONS
+            .into_iter()
+            .find(|supported| *supported == version),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct UpsertAreaRequest {
+    // a new area is created when missing
+    pub area_id: Option<String>,
+    // revision of the area the change was made on, required to update an area
+    pub base_revision: Option<i64>,
+    // fields changed on the area, every field when empty
+    #[serde(default)]
+    pub changed_fields: Vec<AreaField>,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+
+    pub x: f64,
+    pub y: f64,
+    pub estimated_hours: Option<f64>,
+}
+
+/// An area as it is after a change.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct AreaSnapshot {
+    pub area_id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+
+    pub x: f64,
+    pub y: f64,
+    pub estimated_hours: Option<f64>,
+    pub revision: i64,
+}
+
+impl From<Areas> for AreaSnapshot {
+    fn from(area: Areas) -> AreaSnapshot {
+        AreaSnapshot {
+            area_id: area.id,
+            parent_id: area.parent_id,
+            title: area.title,
+            description: area.description,
+            x: area.x,
+            y: area.y,
+            estimated_hours: area.estimated_hours,
+            revision: area.revision,
+        }
+    }
+}
+
+/// A websocket connected to a roadmap, the same user can be connected more than once.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct EditorPresence {
+    pub connection_id: String,
+    pub username: String,
+    pub joined_at: NaiveDateTime,
+}
+
+/// A frame sent by the client.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ClientRequest {
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Creates the area, or replaces it when `area_id` is given.
+    UpsertArea(UpsertAreaRequest),
+    /// Moves the area and its descendants to the trash.
+    DeleteArea {
+        area_id: String,
+    },
+    /// Changes the position of the area on the canvas.
+    MoveArea {
+        area_id: String,
+        x: f64,
+        y: f64,
+    },
+    /// Makes `parent_id` the parent of `child_id`, or a root area when `parent_id` is null.
+    LinkAreas {
+        child_id: String,
+        parent_id: Option<String>,
+    },
+    /// Where the editor is pointing at on the canvas and which areas they have selected.
+    Cursor {
+        x: f64,
+        y: f64,
+        #[serde(default)]
+        selected_area_ids: Vec<String>,
+    },
+    /// Undoes the latest change of the user on the roadmap that isn't undone or skipped yet.
+    Undo,
+    /// Redoes the latest undo of the user, unless they made another change since.
+    Redo,
+    Ping,
+}
+
+impl ClientMessage {
+    /// Checks the values the database would reject, or store but not give back, before anything
+    /// is written or relayed. The error is meant to be shown to the user.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ClientMessage::UpsertArea(request) => validate_area(
+                &request.title,
+                request.x,
+                request.y,
+                request.estimated_hours,
+            ),
+            ClientMessage::MoveArea { x, y, .. } | ClientMessage::Cursor { x, y, .. } => {
+                validate_position(*x, *y)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A frame sent by the server, `sequence` is set on the snapshot and the changes of the
+/// roadmap.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ServerFrame {
+    pub sequence: Option<u64>,
+    #[serde(flatten)]
+    pub event: ServerEvent,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// First frame of every connection, `editors` are the other websockets already connected to
+    /// the roadmap. `resumed` is set when the changes missed since `resume_from` follow instead
+    /// of a snapshot.
+    Welcome {
+        protocol_version: u32,
+        roadmap_id: String,
+        connection_id: String,
+        editors: Vec<EditorPresence>,
+        stream_id: String,
+        resumed: bool,
+    },
+    /// The roadmap as it is at the sequence of the frame.
+    Snapshot {
+        title: String,
+        description: Option<String>,
+        areas: Vec<AreaSnapshot>,
+    },
+    /// The request was applied, `area_id` is the area it was applied to, which is how clients
+    /// learn the id of the areas they create, and `revision` its revision after the change. The
+    /// sequence of the frame is the sequence of the change.
+    Ack {
+        request_id: Option<String>,
+        area_id: Option<String>,
+        revision: Option<i64>,
+    },
+    /// The update was based on a stale revision and someone else changed `fields` since,
+    /// nothing was changed. `area` is the area as it is now.
+    Conflict {
+        request_id: Option<String>,
+        fields: Vec<AreaField>,
+        area: AreaSnapshot,
+    },
+    /// The request was rejected, nothing was changed.
+    Error {
+        request_id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+    Pong {
+        request_id: Option<String>,
+    },
+    /// An area was created or replaced by `editor`.
+    AreaUpserted {
+        editor: String,
+        area: AreaSnapshot,
+    },
+    /// The area and its descendants were moved to the trash.
+    AreaDeleted {
+        editor: String,
+        area_id: String,
+    },
+    /// The area was moved, its new position is written to the database on the next tick.
+    AreaMoved {
+        editor: String,
+        area_id: String,
+        x: f64,
+        y: f64,
+    },
+    AreasLinked {
+        editor: String,
+        child_id: String,
+        parent_id: Option<String>,
+        revision: i64,
+    },
+    /// The area and its descendants were restored from the trash, they have to be fetched again.
+    AreaRestored {
+        editor: String,
+        area_id: String,
+    },
+    EditorJoined {
+        editor: EditorPresence,
+    },
+    EditorLeft {
+        editor: EditorPresence,
+    },
+    Cursor {
+        connection_id: String,
+        username: String,
+        x: f64,
+        y: f64,
+        selected_area_ids: Vec<String>,
+    },
+    /// The connection fell behind and `missed` changes were dropped, reconnecting with the last
+    /// sequence received catches up on them.
+    Lagged {
+        missed: u64,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // the frame isn't a valid request
+    InvalidMessage,
+    // the roadmap or area doesn't exist or was deleted
+    NotFound,
+    // the parent is in another roadmap, deleted, or would create a cycle
+    InvalidParent,
+    // the undo or redo stack of the user is empty
+    NothingToUndo,
+    NothingToRedo,
+    Internal,
+}
+
+/// The connection reached its maximum lifetime.
+pub const CLOSE_RECONNECT: u16 = 4000;
+/// The session is missing or expired.
+pub const CLOSE_UNAUTHORIZED: u16 = 4401;
+/// The user can't edit the roadmap, or can't anymore, or the token lacks the scope to.
+pub const CLOSE_FORBIDDEN: u16 = 4403;
+/// The roadmap doesn't exist or was deleted.
+pub const CLOSE_NOT_FOUND: u16 = 4404;
+/// The client sent nothing, pongs included, for too long.
+pub const CLOSE_IDLE_TIMEOUT: u16 = 4408;
+
+impl ServerEvent {
+    pub fn error(request_id: Option<String>, code: ErrorCode, message: &str) -> ServerEvent {
+        ServerEvent::Error {
+            request_id,
+            code,
+            message: message.to_string(),
+        }
+    }
+}
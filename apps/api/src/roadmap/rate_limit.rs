@@ -0,0 +1,36 @@
+use std::time::Instant;
+
+/// Token bucket limiting the messages of a websocket. Holds up to a second worth of messages so
+/// short bursts go through, then refills at `per_second`.
+pub struct RateLimit {
+    per_second: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimit {
+    pub fn new(per_second: u32) -> RateLimit {
+        RateLimit {
+            per_second: per_second.into(),
+            tokens: per_second.into(),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a message from the bucket, `false` when it is empty.
+    pub fn acquire(&mut self) -> bool {
+        self.acquire_at(Instant::now())
+    }
+
+    pub fn acquire_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.per_second);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
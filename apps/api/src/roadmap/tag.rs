@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use sqlx::{query, Acquire, PgPool, Postgres};
+
+use crate::utils::generate_random_str;
+
+use super::RoadmapSearch;
+
+const TAG_ID_LENGTH: u8 = 12;
+// TAGS.NAME is VARCHAR(32)
+pub const MAX_TAG_LENGTH: usize = 32;
+pub const MAX_TAGS_PER_ROADMAP: usize = 10;
+// facets are meant for a sidebar, the long tail is not worth counting
+const MAX_FACETS: i64 = 20;
+
+#[derive(Debug)]
+pub struct TagCount {
+    pub name: String,
+    pub roadmap_count: i64,
+}
+
+/// Lowercases the tag and joins its words with dashes, e.g. " Web  Development" becomes
+/// "web-development". `None` when it's empty, too long or has anything other than letters,
+/// digits, dashes, `+`, `#` or `.` (so "c++", "c#" and "node.js" are fine).
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let normalized = tag
+        .split(|c: char| c.is_whitespace() || c == '_')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase();
+
+    if normalized.is_empty()
+        || normalized.chars().count() > MAX_TAG_LENGTH
+        || !normalized
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '#' | '.'))
+    {
+        return None;
+    }
+
+    Some(normalized)
+}
+
+/// Normalizes the tags of a roadmap and drops duplicates, keeping the order they were given in.
+/// The error is meant to be shown to the user.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut seen = HashSet::new();
+    let mut normalized_tags = Vec::new();
+
+    for tag in tags {
+        let normalized = normalize_tag(tag).ok_or(format!(
+            "Invalid tag \"{}\", tags are up to {} letters, digits, dashes, '+', '#' or '.'",
+            tag, MAX_TAG_LENGTH
+        ))?;
+
+        if seen.insert(normalized.clone()) {
+            normalized_tags.push(normalized);
+        }
+    }
+
+    if normalized_tags.len() > MAX_TAGS_PER_ROADMAP {
+        return Err(format!(
+            "A roadmap can have at most {} tags",
+            MAX_TAGS_PER_ROADMAP
+        ));
+    }
+
+    Ok(normalized_tags)
+}
+
+/// Replaces the tags of a roadmap, creating the ones that don't exist yet. `tags` are expected
+/// to be normalized already.
+pub async fn set_roadmap_tags(
+    roadmap_id: &String,
+    tags: &[String],
+    connection: impl Acquire<'_, Database = Postgres>,
+) -> Result<(), sqlx::Error> {
+    // a savepoint when called inside a transaction
+    let mut transaction = connection.begin().await?;
+
+    query!(
+        r#"
+            INSERT INTO TAGS (ID, NAME)
+            SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[])
+            ON CONFLICT (NAME) DO NOTHING
+        "#,
+        &tags
+            .iter()
+            .map(|_| generate_random_str(TAG_ID_LENGTH))
+            .collect::<Vec<_>>(),
+        tags
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    query!(
+        r#"DELETE FROM ROADMAP_TAGS WHERE ROADMAP_ID = $1"#,
+        roadmap_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    query!(
+        r#"
+            INSERT INTO ROADMAP_TAGS (ROADMAP_ID, TAG_ID)
+            SELECT $1, ID FROM TAGS WHERE NAME = ANY($2)
+        "#,
+        roadmap_id,
+        tags
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+/// Tags starting with `prefix` that are used by public roadmaps, the most used first. Tags only
+/// used on drafts, private or unlisted roadmaps are left out so they don't leak.
+pub async fn autocomplete_tags(
+    prefix: &str,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<TagCount>, sqlx::Error> {
+    sqlx::query_as!(
+        TagCount,
+        r#"
+            SELECT T.NAME, COUNT(*) AS "roadmap_count!"
+            FROM TAGS T
+            JOIN ROADMAP_TAGS RT ON RT.TAG_ID = T.ID
+            JOIN ROADMAPS R ON R.ID = RT.ROADMAP_ID
+            WHERE
+                T.NAME LIKE $1 || '%' AND
+                R.PUBLISHED = TRUE AND
+                R.VISIBILITY = 'public' AND
+                R.DELETED_AT IS NULL
+            GROUP BY T.NAME
+            ORDER BY 2 DESC, T.NAME
+            LIMIT $2
+        "#,
+        prefix,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Number of roadmaps per tag among every roadmap matching the search, not only the current
+/// page. Both use the `SEARCH_ROADMAPS` function for the filter.
+pub async fn find_roadmap_tag_facets(
+    search: &RoadmapSearch,
+    pool: &PgPool,
+) -> Result<Vec<TagCount>, sqlx::Error> {
+    sqlx::query_as!(
+        TagCount,
+        r#"
+            SELECT T.NAME, COUNT(*) AS "roadmap_count!"
+            FROM SEARCH_ROADMAPS($1, $2, $4, $5, $6, $7) R
+            JOIN ROADMAP_TAGS RT ON RT.ROADMAP_ID = R.ID
+            JOIN TAGS T ON T.ID = RT.TAG_ID
+            GROUP BY T.NAME
+            ORDER BY 2 DESC, T.NAME
+            LIMIT $3
+        "#,
+        format!("%{}%", search.title),
+        &search.tags,
+        MAX_FACETS,
+        search.difficulty.map(|difficulty| difficulty.as_str()),
+        search.content_language,
+        search.min_hours,
+        search.max_hours
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,1208 @@
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use chrono::Utc;
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::{
+    auth::{
+        extractor::AuthenticatedUser, is_session_active, role::find_user_access,
+        token::find_active_personal_access_token,
+    },
+    config::WebsocketConfig,
+    router_common::RouterGlobalState,
+    utils::generate_random_str,
+};
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+
+use std::time::Duration;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{self, Instant},
+};
+
+use super::{
+    add_areas,
+    batch::MoveBatch,
+    codec::{Codec, Frame},
+    delete_area, get_area, get_roadmap_by_id, get_roadmap_role,
+    history::{
+        add_area_change, changed_fields, find_redo_target, find_undo_target, AreaChange,
+        ChangeKind, ChangeSource, NewAreaChange,
+    },
+    hub::{ResumePoint, RoadmapChange},
+    is_valid_parent, link_areas,
+    protocol::{
+        AreaSnapshot, ClientMessage, ClientRequest, EditorPresence, ErrorCode, ServerEvent,
+        ServerFrame, UpsertAreaRequest, CLOSE_FORBIDDEN, CLOSE_IDLE_TIMEOUT, CLOSE_NOT_FOUND,
+        CLOSE_RECONNECT, CLOSE_UNAUTHORIZED,
+    },
+    rate_limit::RateLimit,
+    restore_area, roadmap_exist, update_area, AreaField, AreaUpdate, Areas, MovedArea, RoadmapRole,
+    DEFAULT_ID_LENGTH,
+};
+
+const CONNECTION_ID_LENGTH: u8 = 16;
+
+// what came of a request that wasn't rejected
+enum MessageOutcome {
+    // `change` is broadcast to the other editors and `history` logged
+    Applied {
+        area_id: String,
+        revision: Option<i64>,
+        change: ServerEvent,
+        history: Option<Box<NewAreaChange>>,
+    },
+    Conflict {
+        fields: Vec<AreaField>,
+        area: AreaSnapshot,
+    },
+    // the target of an undo or redo can't be reverted anymore, `skip` is logged so the next one
+    // moves past it and the request is answered with `outcome`
+    Skipped {
+        skip: Box<NewAreaChange>,
+        outcome: Box<MessageResult>,
+    },
+    // moves, written and broadcast with the next flush
+    Buffered {
+        area_id: String,
+    },
+    // pings and cursors, answered by the caller
+    Ignored,
+}
+
+type MessageResult = Result<MessageOutcome, (ErrorCode, &'static str)>;
+
+// who is on the other end of the socket
+struct EditorConnection {
+    connection_id: String,
+    roadmap_id: String,
+    username: String,
+    credential: Credential,
+    codec: Codec,
+}
+
+// what the editor connected with, it can expire or be revoked while the connection is open
+enum Credential {
+    Session(String),
+    PersonalAccessToken(String),
+}
+
+impl Credential {
+    fn of(user: AuthenticatedUser) -> Credential {
+        match (user.personal_access_token_id, user.session_token) {
+            (Some(token_id), _) => Credential::PersonalAccessToken(token_id),
+            (None, session_token) => Credential::Session(session_token.unwrap_or_default()),
+        }
+    }
+}
+
+// what is left of a request that can't be parsed
+#[derive(Deserialize)]
+struct RequestId {
+    request_id: Option<String>,
+}
+
+async fn send_event(
+    socket: &mut WebSocket,
+    codec: &Codec,
+    sequence: Option<u64>,
+    event: ServerEvent,
+) -> Result<(), axum::Error> {
+    let message = match codec.encode(&ServerFrame { sequence, event }) {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(data) => Message::Binary(data),
+    };
+
+    socket.send(message).await
+}
+
+// the request id is echoed even when the rest of the frame can't be parsed
+fn parse_client_request(
+    codec: &Codec,
+    frame: &Frame,
+) -> Result<ClientRequest, (Option<String>, String)> {
+    codec.decode(frame).map_err(|err| {
+        let request_id = codec
+            .decode::<RequestId>(frame)
+            .ok()
+            .and_then(|request| request.request_id);
+
+        (request_id, format!("Invalid request: {}", err))
+    })
+}
+
+async fn check_parent(
+    roadmap_id: &String,
+    area_id: Option<&String>,
+    parent_id: Option<&String>,
+    connection: &mut PgConnection,
+) -> Result<(), (ErrorCode, &'static str)> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    match is_valid_parent(roadmap_id, area_id, parent_id, connection).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            ErrorCode::InvalidParent,
+            "Parent has to be another area of the roadmap that isn't one of its descendants",
+        )),
+        Err(err) => {
+            error!("[roadmap_area_websocket] {}", err);
+            Err((ErrorCode::Internal, "Fail checking parent please try again"))
+        }
+    }
+}
+
+// no rows affected means the area doesn't exist in this roadmap or was deleted
+fn check_affected(
+    result: Result<sqlx::postgres::PgQueryResult, sqlx::Error>,
+    failure: &'static str,
+) -> Result<(), (ErrorCode, &'static str)> {
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err((ErrorCode::NotFound, "Area not found")),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("[roadmap_area_websocket] {}", err);
+            Err((ErrorCode::Internal, failure))
+        }
+    }
+}
+
+// the new revision of the area, `None` when it doesn't exist in this roadmap or was deleted
+fn check_found(
+    result: Result<Option<i64>, sqlx::Error>,
+    failure: &'static str,
+) -> Result<i64, (ErrorCode, &'static str)> {
+    match result {
+        Ok(Some(revision)) => Ok(revision),
+        Ok(None) => Err((ErrorCode::NotFound, "Area not found")),
+        Err(err) => {
+            error!("[roadmap_area_websocket] {}", err);
+            Err((ErrorCode::Internal, failure))
+        }
+    }
+}
+
+async fn upsert_area(
+    editor: &EditorConnection,
+    request: UpsertAreaRequest,
+    connection: &mut PgConnection,
+) -> MessageResult {
+    let roadmap_id = &editor.roadmap_id;
+    check_parent(
+        roadmap_id,
+        request.area_id.as_ref(),
+        request.parent_id.as_ref(),
+        connection,
+    )
+    .await?;
+
+    let area = Areas {
+        id: request
+            .area_id
+            .clone()
+            .unwrap_or_else(|| generate_random_str(DEFAULT_ID_LENGTH)),
+        parent_id: request.parent_id,
+        roadmap_id: roadmap_id.to_string(),
+        title: request.title,
+        description: request.description,
+        x: request.x,
+        y: request.y,
+        estimated_hours: request.estimated_hours,
+        revision: 1,
+        created_at: None,
+        updated_at: Some(Utc::now().naive_utc()),
+    };
+
+    let (area, history) = match (request.area_id, request.base_revision) {
+        (None, _) => {
+            check_affected(
+                add_areas(area.clone(), connection).await,
+                "Fail creating area please try again",
+            )?;
+            let history = NewAreaChange {
+                area_id: area.id.clone(),
+                kind: ChangeKind::Create,
+                source: ChangeSource::Edit,
+                target_id: None,
+                before: None,
+                after: Some(area.clone().into()),
+            };
+            (area, Some(Box::new(history)))
+        }
+        (Some(_), None) => {
+            return Err((
+                ErrorCode::InvalidMessage,
+                "base_revision is required to update an area",
+            ))
+        }
+        (Some(_), Some(base_revision)) => {
+            let fields = if request.changed_fields.is_empty() {
+                AreaField::ALL.to_vec()
+            } else {
+                request.changed_fields
+            };
+
+            match update_area(&area, base_revision, &fields, connection).await {
+                // nothing to log when every field already had its value
+                Ok(AreaUpdate::Applied { before, area }) if before.revision == area.revision => {
+                    (area, None)
+                }
+                Ok(AreaUpdate::Applied { before, area }) => {
+                    let history = NewAreaChange {
+                        area_id: area.id.clone(),
+                        kind: ChangeKind::Update,
+                        source: ChangeSource::Edit,
+                        target_id: None,
+                        before: Some(before.into()),
+                        after: Some(area.clone().into()),
+                    };
+                    (area, Some(Box::new(history)))
+                }
+                Ok(AreaUpdate::Conflict { fields, current }) => {
+                    return Ok(MessageOutcome::Conflict {
+                        fields,
+                        area: current.into(),
+                    })
+                }
+                Err(sqlx::Error::RowNotFound) => {
+                    return Err((ErrorCode::NotFound, "Area not found"))
+                }
+                Err(err) => {
+                    error!("[roadmap_area_websocket] {}", err);
+                    return Err((ErrorCode::Internal, "Fail updating area please try again"));
+                }
+            }
+        }
+    };
+
+    Ok(MessageOutcome::Applied {
+        area_id: area.id.clone(),
+        revision: Some(area.revision),
+        change: ServerEvent::AreaUpserted {
+            editor: editor.username.clone(),
+            area: area.into(),
+        },
+        history,
+    })
+}
+
+// the area as it is now, `None` when it doesn't exist in this roadmap or was deleted
+async fn load_area(
+    roadmap_id: &String,
+    area_id: &String,
+    connection: &mut PgConnection,
+) -> Result<Option<Areas>, (ErrorCode, &'static str)> {
+    get_area(roadmap_id, area_id, connection)
+        .await
+        .map_err(|err| {
+            error!("[roadmap_area_websocket] {}", err);
+            (ErrorCode::Internal, "Fail loading area please try again")
+        })
+}
+
+// applies the inverse of `target`, undoing it or redoing the change it undid
+async fn revert_change(
+    editor: &EditorConnection,
+    target: AreaChange,
+    source: ChangeSource,
+    connection: &mut PgConnection,
+) -> MessageResult {
+    let roadmap_id = &editor.roadmap_id;
+    let area_id = target.area_id;
+    let current = load_area(roadmap_id, &area_id, connection).await?;
+    let history = |kind, before: Option<Areas>, after: Option<Areas>| {
+        Some(Box::new(NewAreaChange {
+            area_id: area_id.clone(),
+            kind,
+            source,
+            target_id: Some(target.id),
+            before: before.map(AreaSnapshot::from),
+            after: after.map(AreaSnapshot::from),
+        }))
+    };
+
+    match (target.kind, target.before, target.after) {
+        (ChangeKind::Create | ChangeKind::Restore, _, _) => {
+            let Some(current) = current else {
+                return Err((ErrorCode::NotFound, "Area not found"));
+            };
+            check_affected(
+                delete_area(roadmap_id.to_string(), area_id.clone(), &mut *connection).await,
+                "Fail deleting area please try again",
+            )?;
+
+            Ok(MessageOutcome::Applied {
+                area_id: area_id.clone(),
+                revision: None,
+                change: ServerEvent::AreaDeleted {
+                    editor: editor.username.clone(),
+                    area_id: area_id.clone(),
+                },
+                history: history(ChangeKind::Delete, Some(current), None),
+            })
+        }
+        (ChangeKind::Delete, _, _) => {
+            match restore_area(roadmap_id.to_string(), area_id.clone(), &mut *connection).await {
+                Ok(result) if result.rows_affected() == 0 => {
+                    return Err((
+                        ErrorCode::NotFound,
+                        "Area not found in trash, its parent might need to be restored first",
+                    ))
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!("[roadmap_area_websocket] {}", err);
+                    return Err((ErrorCode::Internal, "Fail restoring area please try again"));
+                }
+            }
+            let restored = load_area(roadmap_id, &area_id, connection).await?;
+
+            Ok(MessageOutcome::Applied {
+                area_id: area_id.clone(),
+                revision: restored.as_ref().map(|area| area.revision),
+                change: ServerEvent::AreaRestored {
+                    editor: editor.username.clone(),
+                    area_id: area_id.clone(),
+                },
+                history: history(ChangeKind::Restore, None, restored),
+            })
+        }
+        (ChangeKind::Update, Some(before), Some(after)) => {
+            let Some(current) = current else {
+                return Err((ErrorCode::NotFound, "Area not found"));
+            };
+
+            // only fields still holding the value the change gave them can be reverted
+            let fields = changed_fields(&before, &after);
+            let conflicts: Vec<AreaField> =
+                changed_fields(&AreaSnapshot::from(current.clone()), &after)
+                    .into_iter()
+                    .filter(|field| fields.contains(field))
+                    .collect();
+            if !conflicts.is_empty() {
+                return Ok(MessageOutcome::Conflict {
+                    fields: conflicts,
+                    area: current.into(),
+                });
+            }
+            if fields.contains(&AreaField::ParentId) {
+                check_parent(
+                    roadmap_id,
+                    Some(&area_id),
+                    before.parent_id.as_ref(),
+                    connection,
+                )
+                .await?;
+            }
+
+            let reverted = Areas {
+                parent_id: before.parent_id,
+                title: before.title,
+                description: before.description,
+                x: before.x,
+                y: before.y,
+                estimated_hours: before.estimated_hours,
+                ..current.clone()
+            };
+            match update_area(&reverted, current.revision, &fields, connection).await {
+                Ok(AreaUpdate::Applied { before, area }) => Ok(MessageOutcome::Applied {
+                    area_id: area_id.clone(),
+                    revision: Some(area.revision),
+                    change: ServerEvent::AreaUpserted {
+                        editor: editor.username.clone(),
+                        area: area.clone().into(),
+                    },
+                    history: history(ChangeKind::Update, Some(before), Some(area)),
+                }),
+                // changed again since it was loaded
+                Ok(AreaUpdate::Conflict { fields, current }) => Ok(MessageOutcome::Conflict {
+                    fields,
+                    area: current.into(),
+                }),
+                Err(sqlx::Error::RowNotFound) => Err((ErrorCode::NotFound, "Area not found")),
+                Err(err) => {
+                    error!("[roadmap_area_websocket] {}", err);
+                    Err((ErrorCode::Internal, "Fail updating area please try again"))
+                }
+            }
+        }
+        // updates are always logged with both snapshots
+        (ChangeKind::Update, _, _) => {
+            error!(
+                "[roadmap_area_websocket] change {} is missing its snapshots",
+                target.id
+            );
+            Err((
+                ErrorCode::Internal,
+                "Fail reverting change please try again",
+            ))
+        }
+    }
+}
+
+async fn undo_or_redo(
+    editor: &EditorConnection,
+    source: ChangeSource,
+    connection: &mut PgConnection,
+) -> MessageResult {
+    let target = match source {
+        ChangeSource::Redo => {
+            find_redo_target(&editor.roadmap_id, &editor.username, &mut *connection).await
+        }
+        _ => find_undo_target(&editor.roadmap_id, &editor.username, &mut *connection).await,
+    };
+
+    match target {
+        Ok(Some(target)) => {
+            let skip = NewAreaChange {
+                area_id: target.area_id.clone(),
+                kind: target.kind,
+                source: ChangeSource::Skip,
+                target_id: Some(target.id),
+                before: None,
+                after: None,
+            };
+
+            match revert_change(editor, target, source, connection).await {
+                // deleted or changed again since, it won't get any better by trying again
+                outcome @ (Ok(MessageOutcome::Conflict { .. })
+                | Err((ErrorCode::NotFound | ErrorCode::InvalidParent, _))) => {
+                    Ok(MessageOutcome::Skipped {
+                        skip: Box::new(skip),
+                        outcome: Box::new(outcome),
+                    })
+                }
+                outcome => outcome,
+            }
+        }
+        Ok(None) if source == ChangeSource::Redo => {
+            Err((ErrorCode::NothingToRedo, "Nothing to redo"))
+        }
+        Ok(None) => Err((ErrorCode::NothingToUndo, "Nothing to undo")),
+        Err(err) => {
+            error!("[roadmap_area_websocket] {}", err);
+            Err((
+                ErrorCode::Internal,
+                "Fail reverting change please try again",
+            ))
+        }
+    }
+}
+
+// writes the moves waiting for the next tick and logs the drags that are over, every drag when
+// `end_drags` is set. The last moves of a drag are written along with its change, a failed
+// write only loses positions. Moves are only broadcast once written, so editors joining in the
+// meantime can't get a snapshot without a move they won't be sent.
+async fn flush_moves(
+    editor: &EditorConnection,
+    moves: &mut MoveBatch,
+    end_drags: bool,
+    router_global_state: &RouterGlobalState,
+) -> Result<(), (ErrorCode, &'static str)> {
+    if moves.is_empty() && !moves.is_dragging() {
+        return Ok(());
+    }
+
+    let moved = write_moves(editor, moves, end_drags, &router_global_state.pool)
+        .await
+        .map_err(|err| {
+            error!(
+                "[roadmap_area_websocket] fail on writing moves of roadmap {} with cause {}",
+                editor.roadmap_id, err
+            );
+            (ErrorCode::Internal, "Fail saving moves please try again")
+        })?;
+
+    for area in moved {
+        router_global_state.roadmap_hub.publish(
+            &editor.roadmap_id,
+            RoadmapChange {
+                connection_id: Some(editor.connection_id.clone()),
+                sequence: None,
+                event: ServerEvent::AreaMoved {
+                    editor: editor.username.clone(),
+                    area_id: area.id,
+                    x: area.x,
+                    y: area.y,
+                },
+            },
+        );
+    }
+
+    Ok(())
+}
+
+async fn write_moves(
+    editor: &EditorConnection,
+    moves: &mut MoveBatch,
+    end_drags: bool,
+    pool: &PgPool,
+) -> Result<Vec<MovedArea>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let moved = moves.flush(&editor.roadmap_id, &mut *transaction).await?;
+
+    for drag in moves.end_drags(end_drags) {
+        let Some(area) = get_area(&editor.roadmap_id, &drag.area_id, &mut *transaction).await?
+        else {
+            // deleted while dragged
+            continue;
+        };
+        // dropped where it was picked up
+        if (area.x, area.y) == (drag.from_x, drag.from_y) {
+            continue;
+        }
+
+        let after = AreaSnapshot::from(area);
+        let before = AreaSnapshot {
+            x: drag.from_x,
+            y: drag.from_y,
+            revision: drag.from_revision,
+            ..after.clone()
+        };
+        let change = NewAreaChange {
+            area_id: drag.area_id,
+            kind: ChangeKind::Update,
+            source: ChangeSource::Edit,
+            target_id: None,
+            before: Some(before),
+            after: Some(after),
+        };
+        add_area_change(
+            &editor.roadmap_id,
+            &editor.username,
+            &change,
+            &mut *transaction,
+        )
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(moved)
+}
+
+// applies the request in `connection`, the transaction of the request
+async fn handle_client_message(
+    editor: &EditorConnection,
+    moves: &mut MoveBatch,
+    message: ClientMessage,
+    connection: &mut PgConnection,
+) -> MessageResult {
+    let roadmap_id = &editor.roadmap_id;
+
+    match message {
+        ClientMessage::UpsertArea(request) => upsert_area(editor, request, connection).await,
+        ClientMessage::DeleteArea { area_id } => {
+            let before = load_area(roadmap_id, &area_id, connection).await?;
+            check_affected(
+                delete_area(roadmap_id.to_string(), area_id.clone(), connection).await,
+                "Fail deleting area please try again",
+            )?;
+
+            Ok(MessageOutcome::Applied {
+                area_id: area_id.clone(),
+                revision: None,
+                change: ServerEvent::AreaDeleted {
+                    editor: editor.username.clone(),
+                    area_id: area_id.clone(),
+                },
+                history: before.map(|before| {
+                    Box::new(NewAreaChange {
+                        area_id,
+                        kind: ChangeKind::Delete,
+                        source: ChangeSource::Edit,
+                        target_id: None,
+                        before: Some(before.into()),
+                        after: None,
+                    })
+                }),
+            })
+        }
+        ClientMessage::MoveArea { area_id, x, y } => {
+            // logged once the drag is over
+            moves.push(area_id.clone(), x, y);
+
+            Ok(MessageOutcome::Buffered { area_id })
+        }
+        ClientMessage::LinkAreas {
+            child_id,
+            parent_id,
+        } => {
+            check_parent(roadmap_id, Some(&child_id), parent_id.as_ref(), connection).await?;
+            let before = load_area(roadmap_id, &child_id, connection).await?;
+            let revision = check_found(
+                link_areas(roadmap_id, &child_id, parent_id.as_ref(), connection).await,
+                "Fail linking areas please try again",
+            )?;
+
+            Ok(MessageOutcome::Applied {
+                area_id: child_id.clone(),
+                revision: Some(revision),
+                change: ServerEvent::AreasLinked {
+                    editor: editor.username.clone(),
+                    child_id: child_id.clone(),
+                    parent_id: parent_id.clone(),
+                    revision,
+                },
+                history: before
+                    .filter(|before| before.parent_id != parent_id)
+                    .map(|before| {
+                        let before = AreaSnapshot::from(before);
+                        Box::new(NewAreaChange {
+                            area_id: child_id,
+                            kind: ChangeKind::Update,
+                            source: ChangeSource::Edit,
+                            target_id: None,
+                            after: Some(AreaSnapshot {
+                                parent_id,
+                                revision,
+                                ..before.clone()
+                            }),
+                            before: Some(before),
+                        })
+                    }),
+            })
+        }
+        ClientMessage::Undo => undo_or_redo(editor, ChangeSource::Undo, connection).await,
+        ClientMessage::Redo => undo_or_redo(editor, ChangeSource::Redo, connection).await,
+        // answered by the caller, nothing is stored
+        ClientMessage::Ping | ClientMessage::Cursor { .. } => Ok(MessageOutcome::Ignored),
+    }
+}
+
+// logs the change made by the transaction of the request and commits them together, the change
+// isn't made when it can't be logged
+async fn commit_change(
+    editor: &EditorConnection,
+    history: Option<&NewAreaChange>,
+    mut transaction: Transaction<'_, Postgres>,
+) -> Result<(), (ErrorCode, &'static str)> {
+    if let Some(history) = history {
+        add_area_change(
+            &editor.roadmap_id,
+            &editor.username,
+            history,
+            &mut *transaction,
+        )
+        .await
+        .map_err(|err| {
+            error!(
+                "[roadmap_area_websocket] fail on recording {} of area {} with cause {}",
+                history.kind.as_str(),
+                history.area_id,
+                err
+            );
+            (ErrorCode::Internal, "Fail saving change please try again")
+        })?;
+    }
+
+    transaction.commit().await.map_err(|err| {
+        error!("[roadmap_area_websocket] {}", err);
+        (ErrorCode::Internal, "Fail saving change please try again")
+    })
+}
+
+// answer to a frame sent by the client with its sequence, `None` when there is nothing to
+// answer
+async fn handle_client_frame(
+    editor: &EditorConnection,
+    moves: &mut MoveBatch,
+    msg: Message,
+    router_global_state: &RouterGlobalState,
+) -> Option<(Option<u64>, ServerEvent)> {
+    let frame = match msg {
+        Message::Text(text) => Frame::Text(text),
+        Message::Binary(data) => Frame::Binary(data),
+        // pings are answered by axum
+        Message::Ping(_) | Message::Pong(_) | Message::Close(_) => return None,
+    };
+
+    let ClientRequest {
+        request_id,
+        message,
+    } = match parse_client_request(&editor.codec, &frame) {
+        Ok(request) => request,
+        Err((request_id, message)) => {
+            error!("[roadmap_area_websocket] fail on parsing request");
+            return Some((
+                None,
+                ServerEvent::error(request_id, ErrorCode::InvalidMessage, &message),
+            ));
+        }
+    };
+
+    if let Err(message) = message.validate() {
+        return Some((
+            None,
+            ServerEvent::error(request_id, ErrorCode::InvalidMessage, &message),
+        ));
+    }
+
+    match message {
+        ClientMessage::Ping => return Some((None, ServerEvent::Pong { request_id })),
+        ClientMessage::Cursor {
+            x,
+            y,
+            selected_area_ids,
+        } => {
+            router_global_state.roadmap_hub.relay(
+                &editor.roadmap_id,
+                RoadmapChange {
+                    connection_id: Some(editor.connection_id.clone()),
+                    sequence: None,
+                    event: ServerEvent::Cursor {
+                        connection_id: editor.connection_id.clone(),
+                        username: editor.username.clone(),
+                        x,
+                        y,
+                        selected_area_ids,
+                    },
+                },
+            );
+            return None;
+        }
+        _ => {}
+    }
+
+    // pending moves are written first so they can't overwrite a change made after them, and
+    // logged so they can be undone in order
+    if !matches!(message, ClientMessage::MoveArea { .. }) {
+        if let Err((code, message)) = flush_moves(editor, moves, true, router_global_state).await {
+            return Some((None, ServerEvent::error(request_id, code, message)));
+        }
+    }
+
+    let mut transaction = match router_global_state.pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            error!("[roadmap_area_websocket] {}", err);
+            return Some((
+                None,
+                ServerEvent::error(
+                    request_id,
+                    ErrorCode::Internal,
+                    "Fail saving change please try again",
+                ),
+            ));
+        }
+    };
+
+    // rejected requests and conflicts roll the transaction back when it's dropped
+    let result = match handle_client_message(editor, moves, message, &mut transaction).await {
+        Ok(MessageOutcome::Applied {
+            area_id,
+            revision,
+            change,
+            history,
+        }) => commit_change(editor, history.as_deref(), transaction)
+            .await
+            .map(|()| MessageOutcome::Applied {
+                area_id,
+                revision,
+                change,
+                history,
+            }),
+        Ok(MessageOutcome::Skipped { skip, outcome }) => {
+            commit_change(editor, Some(&skip), transaction)
+                .await
+                .and(*outcome)
+        }
+        result => result,
+    };
+
+    match result {
+        Ok(MessageOutcome::Applied {
+            area_id,
+            revision,
+            change,
+            ..
+        }) => {
+            let sequence = router_global_state.roadmap_hub.publish(
+                &editor.roadmap_id,
+                RoadmapChange {
+                    connection_id: Some(editor.connection_id.clone()),
+                    sequence: None,
+                    event: change,
+                },
+            );
+
+            // the ack carries the sequence of the change, the editor doesn't get the change
+            Some((
+                sequence,
+                ServerEvent::Ack {
+                    request_id,
+                    area_id: Some(area_id),
+                    revision,
+                },
+            ))
+        }
+        // the sequence of a move is only known once it's written
+        Ok(MessageOutcome::Buffered { area_id }) => Some((
+            None,
+            ServerEvent::Ack {
+                request_id,
+                area_id: Some(area_id),
+                revision: None,
+            },
+        )),
+        Ok(MessageOutcome::Conflict { fields, area }) => Some((
+            None,
+            ServerEvent::Conflict {
+                request_id,
+                fields,
+                area,
+            },
+        )),
+        // skips only hold conflicts and errors
+        Ok(MessageOutcome::Ignored | MessageOutcome::Skipped { .. }) => None,
+        Err((code, message)) => Some((None, ServerEvent::error(request_id, code, message))),
+    }
+}
+
+fn close_frame(code: u16, reason: &str) -> CloseFrame<'static> {
+    CloseFrame {
+        code,
+        reason: reason.to_string().into(),
+    }
+}
+
+async fn close(socket: &mut WebSocket, close_reason: CloseFrame<'static>) {
+    // the client might be gone already
+    let _ = socket.send(Message::Close(Some(close_reason))).await;
+}
+
+// the close frame to send when receiving failed, only messages over `max_message_bytes` get
+// one, refused by tungstenite before being buffered
+fn receive_error_close(err: axum::Error) -> Option<CloseFrame<'static>> {
+    match err.into_inner().downcast::<tungstenite::Error>() {
+        Ok(err) if matches!(*err, tungstenite::Error::Capacity(_)) => {
+            Some(close_frame(close_code::SIZE, "Message too large"))
+        }
+        _ => None,
+    }
+}
+
+// limits every frame of the client is held to, the close frame to send when one isn't. The
+// size is already checked by tungstenite, kept in case the upgrade isn't configured with it
+fn check_frame(
+    codec: &Codec,
+    msg: &Message,
+    rate_limit: &mut RateLimit,
+    websocket_config: &WebsocketConfig,
+) -> Result<(), CloseFrame<'static>> {
+    let (is_text, size) = match msg {
+        Message::Text(text) => (true, text.len()),
+        Message::Binary(data) => (false, data.len()),
+        // control frames are small and answered by axum
+        Message::Ping(_) | Message::Pong(_) | Message::Close(_) => return Ok(()),
+    };
+
+    if size > websocket_config.max_message_bytes as usize {
+        return Err(close_frame(close_code::SIZE, "Message too large"));
+    }
+    if !rate_limit.acquire() {
+        return Err(close_frame(close_code::POLICY, "Too many messages"));
+    }
+    if is_text != codec.is_text() {
+        return Err(close_frame(
+            close_code::UNSUPPORTED,
+            "Frame type doesn't match the negotiated subprotocol",
+        ));
+    }
+
+    Ok(())
+}
+
+// whether the credential the editor connected with is still valid and the account not disabled
+async fn is_authenticated(editor: &EditorConnection, pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let is_valid = match &editor.credential {
+        Credential::Session(token) => is_session_active(token, pool).await?,
+        Credential::PersonalAccessToken(token_id) => {
+            find_active_personal_access_token(token_id, pool)
+                .await?
+                .is_some()
+        }
+    };
+    if !is_valid {
+        return Ok(false);
+    }
+
+    match find_user_access(&editor.username, pool).await {
+        Ok(access) => Ok(access.disabled_at.is_none()),
+        Err(sqlx::Error::RowNotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// the user can log out, be disabled or lose access to the roadmap, or the roadmap be deleted,
+// while connected
+async fn check_access(
+    editor: &EditorConnection,
+    router_global_state: &RouterGlobalState,
+) -> Result<(), CloseFrame<'static>> {
+    match is_authenticated(editor, &router_global_state.pool).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(close_frame(
+                CLOSE_UNAUTHORIZED,
+                "Please login before continuing",
+            ))
+        }
+        // not worth closing every connection over, checked again on the next ping
+        Err(err) => {
+            error!(
+                "[roadmap_area_websocket] fail on authenticating {} with cause {}",
+                editor.username, err
+            );
+            return Ok(());
+        }
+    }
+
+    match get_roadmap_role(
+        editor.roadmap_id.clone(),
+        &editor.username,
+        &router_global_state.pool,
+    )
+    .await
+    {
+        Ok(Some(role)) if role >= RoadmapRole::Editor => Ok(()),
+        Ok(_) => Err(close_frame(
+            CLOSE_FORBIDDEN,
+            "You can't edit the roadmap anymore",
+        )),
+        Err(sqlx::Error::RowNotFound) => Err(close_frame(CLOSE_NOT_FOUND, "Roadmap not found")),
+        // not worth closing every connection over, checked again on the next ping
+        Err(err) => {
+            error!(
+                "[roadmap_area_websocket] fail on checking access of {} with cause {}",
+                editor.username, err
+            );
+            Ok(())
+        }
+    }
+}
+
+// changes published since the client's resume point, it came back before they were dropped
+async fn send_missed_changes(
+    socket: &mut WebSocket,
+    editor: &EditorConnection,
+    missed: Vec<RoadmapChange>,
+) -> Result<(), axum::Error> {
+    for change in missed {
+        send_event(socket, &editor.codec, change.sequence, change.event).await?;
+    }
+
+    Ok(())
+}
+
+// the roadmap as it is now, or why the connection is closed
+async fn load_snapshot(
+    editor: &EditorConnection,
+    router_global_state: &RouterGlobalState,
+) -> Result<ServerEvent, CloseFrame<'static>> {
+    match get_roadmap_by_id(editor.roadmap_id.clone(), &router_global_state.pool).await {
+        Ok(roadmap) => Ok(ServerEvent::Snapshot {
+            title: roadmap.roadmap_title,
+            description: roadmap.roadmap_description,
+            areas: roadmap.areas.into_iter().map(AreaSnapshot::from).collect(),
+        }),
+        // deleted since the connection was opened
+        Err(sqlx::Error::RowNotFound) => Err(close_frame(CLOSE_NOT_FOUND, "Roadmap not found")),
+        Err(err) => {
+            error!(
+                "[roadmap_area_websocket] fail on loading roadmap {} with cause {}",
+                editor.roadmap_id, err
+            );
+            Err(close_frame(
+                close_code::ERROR,
+                "Fail on loading the roadmap, please try again",
+            ))
+        }
+    }
+}
+
+// moves are buffered and written once per tick, dragging an area sends a move on every frame.
+//TODO: @Performance, on the client, it's mandatory to optimize for only sending areas that only
+// changes, don't send data that dont change to reduce load on the network
+pub async fn roadmap_area_websocket(
+    mut socket: WebSocket,
+    router_global_state: RouterGlobalState,
+    roadmap_id: String,
+    user: AuthenticatedUser,
+    protocol_version: u32,
+    codec: Codec,
+    resume: Option<ResumePoint>,
+) {
+    match roadmap_exist(roadmap_id.clone(), &router_global_state.pool).await {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("[roadmap_area_websocket] attempted to modify non existing roadmap");
+            close(
+                &mut socket,
+                close_frame(CLOSE_NOT_FOUND, "Roadmap not found"),
+            )
+            .await;
+            return;
+        }
+        Err(err) => {
+            error!("[roadmap_area_websocket]{}", err);
+            close(
+                &mut socket,
+                close_frame(
+                    close_code::ERROR,
+                    "Fail on connecting the editing the roadmap, please try again",
+                ),
+            )
+            .await;
+            return;
+        }
+    }
+
+    let editor = EditorConnection {
+        connection_id: generate_random_str(CONNECTION_ID_LENGTH),
+        roadmap_id,
+        username: user.username.clone(),
+        credential: Credential::of(user),
+        codec,
+    };
+    // subscribed before the snapshot is taken so no change made after it is missed
+    let (mut changes, joined) = router_global_state.roadmap_hub.subscribe(
+        &editor.roadmap_id,
+        EditorPresence {
+            connection_id: editor.connection_id.clone(),
+            username: editor.username.clone(),
+            joined_at: Utc::now().naive_utc(),
+        },
+        resume.as_ref(),
+    );
+    info!(
+        "[roadmap_area_websocket] {} joined roadmap {}, {} editors connected",
+        editor.username,
+        editor.roadmap_id,
+        joined.editors.len() + 1
+    );
+
+    let welcome = ServerEvent::Welcome {
+        protocol_version,
+        roadmap_id: editor.roadmap_id.clone(),
+        connection_id: editor.connection_id.clone(),
+        editors: joined.editors,
+        stream_id: joined.stream_id,
+        resumed: joined.missed.is_some(),
+    };
+    if send_event(&mut socket, &editor.codec, None, welcome)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let caught_up = match joined.missed {
+        Some(missed) => send_missed_changes(&mut socket, &editor, missed)
+            .await
+            .is_ok(),
+        None => match load_snapshot(&editor, &router_global_state).await {
+            Ok(snapshot) => send_event(&mut socket, &editor.codec, Some(joined.sequence), snapshot)
+                .await
+                .is_ok(),
+            Err(close_reason) => {
+                close(&mut socket, close_reason).await;
+                false
+            }
+        },
+    };
+    if !caught_up {
+        return;
+    }
+
+    let websocket_config = &router_global_state.config.websocket;
+    let mut moves = MoveBatch::new();
+    let mut rate_limit = RateLimit::new(websocket_config.max_messages_per_sec);
+    let mut flush_tick = time::interval(Duration::from_millis(
+        websocket_config.flush_interval_ms.into(),
+    ));
+    let ping_interval = Duration::from_secs(websocket_config.ping_interval_secs.into());
+    let mut ping_tick = time::interval_at(Instant::now() + ping_interval, ping_interval);
+    let idle_timeout = Duration::from_secs(websocket_config.idle_timeout_secs.into());
+    let mut last_received = Instant::now();
+    let lifetime = time::sleep(Duration::from_secs(
+        websocket_config.max_lifetime_secs.into(),
+    ));
+    tokio::pin!(lifetime);
+
+    // the close frame to send, none when the client is gone
+    let close_reason = loop {
+        let frame = tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | None => break None,
+                Some(Err(err)) => break receive_error_close(err),
+                Some(Ok(msg)) => {
+                    last_received = Instant::now();
+                    if let Err(close_reason) =
+                        check_frame(&editor.codec, &msg, &mut rate_limit, websocket_config)
+                    {
+                        break Some(close_reason);
+                    }
+
+                    handle_client_frame(&editor, &mut moves, msg, &router_global_state).await
+                }
+            },
+            _ = flush_tick.tick() => {
+                // already logged, a failed write only loses positions
+                let _ = flush_moves(&editor, &mut moves, false, &router_global_state).await;
+                None
+            },
+            _ = ping_tick.tick() => {
+                if last_received.elapsed() >= idle_timeout {
+                    break Some(close_frame(CLOSE_IDLE_TIMEOUT, "No message received for too long"));
+                }
+                if let Err(close_reason) = check_access(&editor, &router_global_state).await {
+                    break Some(close_reason);
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break None;
+                }
+                None
+            },
+            _ = &mut lifetime => {
+                break Some(close_frame(
+                    CLOSE_RECONNECT,
+                    "Connection lifetime reached, reconnect to resume",
+                ));
+            },
+            change = changes.recv() => match change {
+                Ok(change) if change.connection_id.as_ref() == Some(&editor.connection_id) => None,
+                Ok(change) => Some((change.sequence, change.event)),
+                Err(RecvError::Lagged(missed)) => Some((None, ServerEvent::Lagged { missed })),
+                // the hub only drops the sender we are subscribed to when shutting down
+                Err(RecvError::Closed) => {
+                    break Some(close_frame(close_code::AWAY, "Server is shutting down"));
+                }
+            },
+        };
+
+        if let Some((sequence, event)) = frame {
+            if let Err(err) = send_event(&mut socket, &editor.codec, sequence, event).await {
+                error!("[roadmap_area_websocket] {}", err);
+                break None;
+            }
+        }
+    };
+
+    let _ = flush_moves(&editor, &mut moves, true, &router_global_state).await;
+    if let Some(close_reason) = close_reason {
+        info!(
+            "[roadmap_area_websocket] closing connection of {} on roadmap {} with code {}, {}",
+            editor.username, editor.roadmap_id, close_reason.code, close_reason.reason
+        );
+        close(&mut socket, close_reason).await;
+    }
+}
+
+/// Upgrades only to close right away with the reason the user can't edit the roadmap, browsers
+/// don't expose the status of a failed upgrade.
+pub async fn refuse_connection(mut socket: WebSocket, code: u16, reason: String) {
+    close(&mut socket, close_frame(code, &reason)).await;
+}
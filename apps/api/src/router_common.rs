@@ -1,9 +1,20 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+    response::IntoResponse,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
+use crate::{config::Config, roadmap::hub::RoadmapHub};
+
 #[derive(Clone)]
 pub struct RouterGlobalState {
     pub pool: PgPool,
+    pub config: Arc<Config>,
+    pub roadmap_hub: RoadmapHub,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -12,3 +23,35 @@ pub struct CreateResponse {
     pub message: String,
     pub id: Option<String>,
 }
+
+/// Failed request, rendered as an unsuccessful `CreateResponse` with the given status.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: &str) -> ApiError {
+        ApiError {
+            status,
+            message: String::from(message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response<Body> {
+        let response = serde_json::to_string(&CreateResponse {
+            is_successful: false,
+            message: self.message,
+            id: None,
+        })
+        .unwrap();
+
+        Response::builder()
+            .status(self.status)
+            .body(Body::from(response))
+            .unwrap()
+    }
+}
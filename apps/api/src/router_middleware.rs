@@ -1,7 +1,19 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use axum::{extract::Request, middleware::Next, response::Response};
-use log::info;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use log::{info, warn};
+use reqwest::Url;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{
+    config::CorsConfig,
+    router_common::{ApiError, RouterGlobalState},
+};
 
 pub async fn trace_time(request: Request, next: Next) -> Response {
     let start_time = Instant::now();
@@ -16,3 +28,77 @@ pub async fn trace_time(request: Request, next: Next) -> Response {
     );
     response
 }
+
+/// Origin of the page that made the request, taken from the `Origin` header or the `Referer`
+/// header for older browsers.
+pub fn request_origin(headers: &HeaderMap) -> Option<String> {
+    if let Some(origin) = headers.get(header::ORIGIN) {
+        // unparseable origins are kept so they are rejected instead of treated as missing
+        return Some(origin.to_str().unwrap_or("null").to_string());
+    }
+
+    let referer = headers.get(header::REFERER)?.to_str().ok()?;
+    match Url::parse(referer) {
+        Ok(url) => Some(url.origin().ascii_serialization()),
+        Err(_) => Some(String::from("null")),
+    }
+}
+
+/// Rejects requests made from a browser page outside the allowed origins. Requests without an
+/// origin come from non browser clients and are allowed.
+pub fn check_origin(headers: &HeaderMap, cors: &CorsConfig) -> Result<(), ApiError> {
+    match request_origin(headers) {
+        Some(origin) if !cors.is_allowed_origin(&origin) => {
+            warn!(
+                "[router_middleware] rejected request from origin {}",
+                origin
+            );
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "Origin is not allowed",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Origin checking CSRF protection for state changing requests. Browsers always send the origin
+/// on cross site POST, PUT and DELETE requests, so a request carrying the session cookie without
+/// an origin is rejected as well.
+pub async fn csrf_protection(
+    State(RouterGlobalState { config, .. }): State<RouterGlobalState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method();
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    if let Err(err) = check_origin(headers, &config.cors) {
+        return err.into_response();
+    }
+
+    if request_origin(headers).is_none() && headers.contains_key(header::COOKIE) {
+        return ApiError::new(StatusCode::FORBIDDEN, "Missing request origin").into_response();
+    }
+
+    next.run(request).await
+}
+
+pub fn cors_layer(cors: &CorsConfig) -> CorsLayer {
+    let allowed_origins: Vec<HeaderValue> = cors
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(allowed_origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+        // needed for the session cookie set by login
+        .allow_credentials(true)
+        .max_age(Duration::from_secs(cors.max_age_secs.into()))
+}
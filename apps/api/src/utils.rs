@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 
 pub fn generate_random_str(length: u8) -> String {
@@ -9,3 +11,19 @@ pub fn generate_random_str(length: u8) -> String {
 
     random_string
 }
+
+pub fn parse_cookie_value(value: String) -> HashMap<String, String> {
+    let separated_kv = value.split(";");
+    let mut map = HashMap::new();
+
+    for unstructured_kv in separated_kv {
+        let mut kv = unstructured_kv.split("=");
+
+        if let (Some(key), Some(value)) = (kv.next(), kv.next()) {
+            // browsers separate cookies with "; " so the key needs to be trimmed
+            map.insert(String::from(key.trim()), String::from(value));
+        }
+    }
+
+    map
+}